@@ -0,0 +1,18 @@
+CREATE TABLE IF NOT EXISTS sessions(
+    id serial NOT NULL PRIMARY KEY,
+    user_id int NOT NULL REFERENCES users(id) ON DELETE CASCADE ON UPDATE CASCADE,
+    token_hash text NOT NULL UNIQUE,
+    device text,
+    create_ts timestamptz NOT NULL DEFAULT now(),
+    last_seen_ts timestamptz NOT NULL DEFAULT now(),
+    expire_ts timestamptz
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions(user_id);
+
+-- Existing tokens become primary sessions without expiration. Empty and default tokens
+-- and tokens shared by several users don't identify a user, so they get no session.
+INSERT INTO sessions(user_id, token_hash)
+SELECT id, encode(sha256(convert_to(token, 'UTF8')), 'hex') FROM users
+WHERE token NOT IN ('', 'dummy')
+AND token IN (SELECT token FROM users GROUP BY token HAVING count(*) = 1);
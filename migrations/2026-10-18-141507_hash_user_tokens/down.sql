@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+-- Keyed hashes can not be restored, such sessions are removed
+DELETE FROM sessions WHERE unkeyed_hash IS NULL;
+UPDATE sessions SET token_hash = unkeyed_hash;
+ALTER TABLE sessions DROP COLUMN unkeyed_hash;
+
+-- Hashed tokens can not be restored either, users get random tokens and have to update them
+DROP INDEX users_token_hash_idx;
+ALTER TABLE users DROP COLUMN token_hash;
+ALTER TABLE users DROP COLUMN unkeyed_hash;
+ALTER TABLE users ADD COLUMN token text NOT NULL DEFAULT 'dummy';
+UPDATE users SET token = md5(random()::text || id::text);
+CREATE INDEX users_token_idx ON users(token);
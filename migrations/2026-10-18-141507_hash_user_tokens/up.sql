@@ -0,0 +1,17 @@
+-- Tokens are hashed here and the hashes are keyed with the server secret on startup
+-- (see session::key_tokens), so plain tokens don't outlive the migration
+ALTER TABLE users ADD COLUMN token_hash text;
+ALTER TABLE users ADD COLUMN unkeyed_hash text;
+
+-- Empty, default and shared tokens don't identify a user, such users have to update them
+UPDATE users SET unkeyed_hash = encode(sha256(convert_to(token, 'UTF8')), 'hex')
+WHERE token NOT IN ('', 'dummy')
+AND token IN (SELECT token FROM users GROUP BY token HAVING count(*) = 1);
+
+DROP INDEX IF EXISTS users_token_idx;
+ALTER TABLE users DROP COLUMN token;
+CREATE UNIQUE INDEX users_token_hash_idx ON users(token_hash);
+
+-- Sessions match nothing until their hashes are keyed
+ALTER TABLE sessions ADD COLUMN unkeyed_hash text;
+UPDATE sessions SET unkeyed_hash = token_hash, token_hash = 'unkeyed:' || id;
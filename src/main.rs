@@ -1,26 +1,41 @@
-extern crate diesel_migrations;
 use diesel_migrations::MigrationHarness;
-use log::info;
-use ocean::api::user_cache;
+use log::{error, info};
+use ocean::api::router;
 use ocean::app;
+use ocean::config;
 use ocean::db;
+use ocean::logger;
+use ocean::session;
+use ocean::shutdown;
+use std::process::ExitCode;
 
-use diesel_migrations::{EmbeddedMigrations, embed_migrations};
+fn main() -> Result<ExitCode, Box<dyn std::error::Error + Send + Sync>> {
+    // Log format comes from config, so config errors are always logged as text
+    if let Err(e) = config::init() {
+        logger::init(&config::LogFormat::Text);
+        error!("{}", e);
+        std::process::exit(1);
+    }
 
-pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    env_logger::builder().format_timestamp(None).init();
+    logger::init(&config::CONFIG.log.format);
 
     info!("Ocean started");
 
-    let mut db = db::Db::new();
-    db.conn.run_pending_migrations(MIGRATIONS)?;
+    if let Err(e) = db::init() {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+
+    router::init();
+
+    let mut db = db::Db::new()?;
+    db.conn.run_pending_migrations(db::MIGRATIONS)?;
+    session::key_tokens(&mut db.conn)?;
 
-    user_cache::init(db);
+    let runtime = tokio::runtime::Runtime::new()?;
+    let result = runtime.block_on(app::App::new().start());
 
-    let app = app::App::new();
-    app.start().await?;
-    Ok(())
+    // Handlers still running on blocking pool don't delay exit past shutdown deadline
+    runtime.shutdown_timeout(shutdown::remaining());
+    result
 }
@@ -1,70 +1,37 @@
 use crate::config;
+use crate::controller::ownership::Content;
 use crate::types::UserCode;
 
-pub fn authorize(method: &str, user_code: &UserCode) -> bool {
-    let anonym_allowed = config::CONFIG.server.anonym_allowed;
+// Access policy of method, every registered method must have one
+#[derive(Clone, Copy, Debug)]
+pub enum Access {
+    Anonym,
+    AnonymIfAllowed, // Anonym when `server.anonym_allowed` is set, otherwise User
+    User,
+    Admin,
+    Author(Content), // User that created content or admin
+}
 
-    let allowed_code = match method {
-        "mandela.create" => {
-            if anonym_allowed {
-                UserCode::Anonym
-            } else {
-                UserCode::User
-            }
-        }
-        "mandela.update" => UserCode::User,
-        "mandela.delete" => UserCode::Admin,
-        "mandela.mark" => UserCode::User,
-        "mandela.vote" => UserCode::User,
-        "mandela.updateTrash" => UserCode::Admin,
-        "mandela.getVoteUsers" => UserCode::Admin,
-        "user.logout" => UserCode::User,
-        "user.update" => UserCode::Admin,
-        "user.delete" => UserCode::Admin,
-        "user.updateToken" => UserCode::User,
-        "user.updateProfile" => UserCode::User,
-        "comment.create" => {
-            if anonym_allowed {
-                UserCode::Anonym
-            } else {
-                UserCode::User
-            }
-        }
-        "comment.update" => UserCode::User,
-        "comment.delete" => UserCode::User,
-        "forum.category.create" => UserCode::Admin,
-        "forum.category.update" => UserCode::Admin,
-        "forum.category.delete" => UserCode::Admin,
-        "forum.section.create" => UserCode::Admin,
-        "forum.section.update" => UserCode::Admin,
-        "forums.section.delete" => UserCode::Admin,
-        "forum.topic.create" => {
-            if anonym_allowed {
-                UserCode::Anonym
-            } else {
-                UserCode::User
+impl Access {
+    // Minimal user code allowed to call method
+    pub fn user_code(&self) -> UserCode {
+        match self {
+            Access::Anonym => UserCode::Anonym,
+            Access::AnonymIfAllowed => {
+                if config::CONFIG.server.anonym_allowed {
+                    UserCode::Anonym
+                } else {
+                    UserCode::User
+                }
             }
+            Access::User | Access::Author(_) => UserCode::User,
+            Access::Admin => UserCode::Admin,
         }
-        "forum.topic.update" => UserCode::User,
-        "forums.topic.delete" => UserCode::User,
-        "forums.topic.vote" => UserCode::User,
-        "forums.topic.getVoteUsers" => UserCode::Admin,
-        "forum.post.create" => {
-            if anonym_allowed {
-                UserCode::Anonym
-            } else {
-                UserCode::User
-            }
-        }
-        "forum.post.update" => UserCode::User,
-        "forums.post.delete" => UserCode::User,
-        "like.create" => UserCode::User,
-        "like.delete" => UserCode::User,
-        "like.getUsers" => UserCode::Admin,
-        _ => UserCode::Anonym,
-    };
+    }
+}
 
-    user_security_order(user_code) >= user_security_order(&allowed_code)
+pub fn authorize(access: Access, user_code: &UserCode) -> bool {
+    user_security_order(user_code) >= user_security_order(&access.user_code())
 }
 
 fn user_security_order(user_code: &UserCode) -> u8 {
@@ -0,0 +1,113 @@
+use crate::config;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Encoding {
+    Brotli,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            _ => None,
+        }
+    }
+}
+
+// Encoding with the highest quality value from `Accept-Encoding`.
+// Ties are resolved by server preference: brotli, zstd, gzip.
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    if !config::CONFIG.server.compression.enabled {
+        return None;
+    }
+
+    let mut best: Option<(Encoding, f32)> = None;
+
+    for item in headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+    {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim().to_lowercase();
+
+        let quality = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        let Some(encoding) = Encoding::from_name(&name) else {
+            continue;
+        };
+
+        if quality <= 0.0 {
+            continue;
+        }
+
+        let better = match best {
+            Some((current, q)) => {
+                quality > q || (quality == q && (encoding as u8) < (current as u8))
+            }
+            None => true,
+        };
+
+        if better {
+            best = Some((encoding, quality));
+        }
+    }
+
+    best.map(|(encoding, _)| encoding)
+}
+
+// Small bodies are sent as is, compression doesn't pay off for them
+pub fn compress(encoding: Encoding, data: &[u8]) -> std::io::Result<Option<Vec<u8>>> {
+    if data.len() < config::CONFIG.server.compression.min_size {
+        return Ok(None);
+    }
+
+    let compressed = match encoding {
+        Encoding::Brotli => {
+            const BUFFER_SIZE: usize = 4096;
+            const QUALITY: u32 = 5;
+            const WINDOW_SIZE: u32 = 22;
+
+            let mut output = Vec::new();
+            let mut writer =
+                brotli::CompressorWriter::new(&mut output, BUFFER_SIZE, QUALITY, WINDOW_SIZE);
+            writer.write_all(data)?;
+            drop(writer);
+            output
+        }
+        Encoding::Zstd => zstd::encode_all(data, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+        Encoding::Gzip => {
+            let mut encoder =
+                flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            encoder.finish()?
+        }
+    };
+
+    Ok(Some(compressed))
+}
+
+pub fn apply(encoding: Encoding, headers: &mut HeaderMap) {
+    headers.insert(
+        header::CONTENT_ENCODING,
+        HeaderValue::from_static(encoding.name()),
+    );
+}
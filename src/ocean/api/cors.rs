@@ -0,0 +1,73 @@
+use crate::api::request_log;
+use crate::config;
+use hyper::header::{self, HeaderMap, HeaderValue};
+use hyper::{Response, StatusCode};
+
+const ALLOWED_METHODS: &str = "POST, OPTIONS";
+
+// Origin that is echoed back to the browser if it is in configured list.
+// Configured `*` allows any origin.
+pub fn allowed_origin(headers: &HeaderMap) -> Option<HeaderValue> {
+    let origin = headers.get(header::ORIGIN)?;
+    let cors = &config::CONFIG.server.cors;
+
+    let allowed = if cors.allowed_origins.is_empty() {
+        origin == config::CONFIG.frontend.domen.as_str()
+    } else {
+        cors.allowed_origins
+            .iter()
+            .any(|o| o == "*" || origin == o.as_str())
+    };
+
+    allowed.then(|| origin.clone())
+}
+
+pub fn preflight<B>(body: B) -> Response<B> {
+    let cors = &config::CONFIG.server.cors;
+
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header(header::ACCESS_CONTROL_ALLOW_METHODS, ALLOWED_METHODS)
+        .header(
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            cors.allowed_headers.join(", "),
+        )
+        .header(header::ACCESS_CONTROL_MAX_AGE, cors.max_age)
+        .body(body)
+        .unwrap()
+}
+
+pub fn apply(origin: Option<HeaderValue>, headers: &mut HeaderMap) {
+    headers.append(header::VARY, HeaderValue::from_static("Origin"));
+
+    if let Some(o) = origin {
+        headers.insert(header::ACCESS_CONTROL_ALLOW_ORIGIN, o);
+        // Frontend reads request id to report it with errors
+        headers.insert(
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            HeaderValue::from_name(request_log::REQUEST_ID_HEADER),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_id_is_exposed() {
+        let mut headers = HeaderMap::new();
+        apply(
+            Some(HeaderValue::from_static("https://example.com")),
+            &mut headers,
+        );
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_EXPOSE_HEADERS],
+            "x-request-id"
+        );
+
+        let mut headers = HeaderMap::new();
+        apply(None, &mut headers);
+        assert!(!headers.contains_key(header::ACCESS_CONTROL_EXPOSE_HEADERS));
+    }
+}
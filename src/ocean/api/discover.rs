@@ -0,0 +1,85 @@
+use crate::types::UserCode;
+use schemars::generate::{SchemaGenerator, SchemaSettings};
+use schemars::{JsonSchema, Schema};
+use serde_json::{Value, json};
+
+pub const OPENRPC_VERSION: &str = "1.3.2";
+
+pub type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+pub fn schema<T: JsonSchema>(generator: &mut SchemaGenerator) -> Schema {
+    generator.subschema_for::<T>()
+}
+
+pub struct Method<'a> {
+    pub name: &'a str,
+    pub params: SchemaFn,
+    pub result: SchemaFn,
+    pub user_code: UserCode,
+}
+
+pub fn document(mut methods: Vec<Method>) -> Value {
+    methods.sort_by_key(|m| m.name);
+
+    // Field names may differ for deserialization and serialization
+    let settings = SchemaSettings::draft2020_12().with(|s| s.inline_subschemas = true);
+    let mut params_generator = settings.clone().for_deserialize().into_generator();
+    let mut result_generator = settings.for_serialize().into_generator();
+
+    let methods: Vec<Value> = methods
+        .iter()
+        .map(|m| {
+            json!({
+                "name": m.name,
+                "paramStructure": "by-name",
+                "params": params(&(m.params)(&mut params_generator)),
+                "result": {
+                    "name": "result",
+                    "schema": (m.result)(&mut result_generator),
+                },
+                "x-user-code": user_code(&m.user_code),
+            })
+        })
+        .collect();
+
+    json!({
+        "openrpc": OPENRPC_VERSION,
+        "info": {
+            "title": "Ocean API",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "methods": methods,
+    })
+}
+
+// Each property of params object is described as a separate named param
+fn params(schema: &Schema) -> Vec<Value> {
+    let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+        return Vec::new();
+    };
+
+    let required: Vec<&str> = schema
+        .get("required")
+        .and_then(Value::as_array)
+        .map(|list| list.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    properties
+        .iter()
+        .map(|(name, schema)| {
+            json!({
+                "name": name,
+                "required": required.contains(&name.as_str()),
+                "schema": schema,
+            })
+        })
+        .collect()
+}
+
+fn user_code(code: &UserCode) -> &'static str {
+    match code {
+        UserCode::Admin => "admin",
+        UserCode::User => "user",
+        UserCode::Anonym => "anonym",
+    }
+}
@@ -13,6 +13,12 @@ pub const PARAMETER_NOT_FOUND: ErrorCode = 4;
 pub const INTERNAL_SERVER_ERROR: ErrorCode = 5;
 pub const INVALID_PARAMETER: ErrorCode = 6;
 pub const RECORD_NOT_FOUND: ErrorCode = 7;
+pub const INVALID_REQUEST: ErrorCode = 8;
+pub const DATABASE_UNAVAILABLE: ErrorCode = 9;
+pub const EXECUTION_TIMEOUT: ErrorCode = 10;
+pub const RATE_LIMIT_EXCEEDED: ErrorCode = 11; // data: seconds to wait before retry
+pub const CONFLICT: ErrorCode = 12; // data: violated constraint
+pub const INVALID_REFERENCE: ErrorCode = 13; // data: violated constraint
 
 // User (100..199)
 pub const WRONG_USER_PASSWORD: ErrorCode = 100;
@@ -29,6 +35,12 @@ static ERROR_MESSAGES: LazyLock<HashMap<ErrorCode, &'static str>> = LazyLock::ne
     m.insert(INTERNAL_SERVER_ERROR, "Internal server error");
     m.insert(INVALID_PARAMETER, "Invalid parameter");
     m.insert(RECORD_NOT_FOUND, "Record not found");
+    m.insert(INVALID_REQUEST, "Invalid request");
+    m.insert(DATABASE_UNAVAILABLE, "Database unavailable");
+    m.insert(EXECUTION_TIMEOUT, "Execution timeout");
+    m.insert(RATE_LIMIT_EXCEEDED, "Rate limit exceeded");
+    m.insert(CONFLICT, "Conflict");
+    m.insert(INVALID_REFERENCE, "Invalid reference");
 
     m.insert(WRONG_USER_PASSWORD, "Wrong user password");
     m.insert(NEXT_ID_EXPIRED, "Next id expired");
@@ -37,7 +49,7 @@ static ERROR_MESSAGES: LazyLock<HashMap<ErrorCode, &'static str>> = LazyLock::ne
     m
 });
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Error {
     code: ErrorCode,
     message: String,
@@ -78,10 +90,10 @@ impl fmt::Display for Error {
     }
 }
 
-pub fn make_error(code: ErrorCode) -> Box<dyn error::Error> {
-    Box::new(Error::new(code, None))
+pub fn make_error(code: ErrorCode) -> Error {
+    Error::new(code, None)
 }
 
-pub fn make_error_data(code: ErrorCode, data: &str) -> Box<dyn error::Error> {
-    Box::new(Error::new(code, Some(data.to_string())))
+pub fn make_error_data(code: ErrorCode, data: &str) -> Error {
+    Error::new(code, Some(data.to_string()))
 }
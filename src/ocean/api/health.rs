@@ -0,0 +1,146 @@
+use crate::db;
+use diesel::prelude::*;
+use diesel_migrations::MigrationHarness;
+use hyper::StatusCode;
+use log::error;
+use serde::Serialize;
+use std::time;
+use tokio::sync::Mutex;
+
+// Probes are frequent, so result of the last check is reused for a while
+const READY_TTL: time::Duration = time::Duration::from_secs(5);
+
+const UNAVAILABLE: &str = "Unavailable";
+const NOT_CHECKED: &str = "Not checked";
+
+static READY: Mutex<Option<(time::Instant, StatusCode, String)>> = Mutex::const_new(None);
+
+#[derive(Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Status {
+    Ok,
+    Fail,
+}
+
+#[derive(Serialize)]
+struct Component {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+impl Component {
+    fn ok() -> Self {
+        Component {
+            status: Status::Ok,
+            error: None,
+        }
+    }
+
+    // Details are only logged, the endpoint is usually public
+    fn fail(error: &'static str) -> Self {
+        Component {
+            status: Status::Fail,
+            error: Some(error),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Components {
+    database: Component,
+    migrations: Component,
+}
+
+#[derive(Serialize)]
+struct Report<T: Serialize> {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    components: Option<T>,
+}
+
+// GET /health: the process is up and serves requests
+pub fn live() -> (StatusCode, String) {
+    let report = Report::<Components> {
+        status: Status::Ok,
+        components: None,
+    };
+
+    (StatusCode::OK, serde_json::to_string(&report).unwrap())
+}
+
+// GET /ready: all components needed to serve API are available
+pub async fn ready() -> (StatusCode, String) {
+    // Concurrent probes wait for the same check
+    let mut last = READY.lock().await;
+
+    if let Some((checked, code, body)) = &*last
+        && checked.elapsed() < READY_TTL
+    {
+        return (*code, body.clone());
+    }
+
+    let (code, body) = check().await;
+    *last = Some((time::Instant::now(), code, body.clone()));
+    (code, body)
+}
+
+async fn check() -> (StatusCode, String) {
+    let (database, migrations) = tokio::task::spawn_blocking(check_database)
+        .await
+        .unwrap_or_else(|e| {
+            error!("Readiness check failed: {}", e);
+            (Component::fail(UNAVAILABLE), Component::fail(NOT_CHECKED))
+        });
+
+    let components = Components {
+        database,
+        migrations,
+    };
+
+    let ready = [&components.database, &components.migrations]
+        .iter()
+        .all(|c| c.status == Status::Ok);
+
+    let report = Report {
+        status: if ready { Status::Ok } else { Status::Fail },
+        components: Some(components),
+    };
+
+    let code = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (code, serde_json::to_string(&report).unwrap())
+}
+
+fn check_database() -> (Component, Component) {
+    let mut db = match db::Db::new() {
+        Ok(db) => db,
+        Err(e) => {
+            error!("Readiness database pool error: {}", e);
+            return (Component::fail(UNAVAILABLE), Component::fail(NOT_CHECKED));
+        }
+    };
+
+    let database = match diesel::sql_query("SELECT 1").execute(&mut db.conn) {
+        Ok(_) => Component::ok(),
+        Err(e) => {
+            error!("Readiness database error: {}", e);
+            Component::fail(UNAVAILABLE)
+        }
+    };
+
+    let migrations = match db.conn.has_pending_migration(db::MIGRATIONS) {
+        Ok(false) => Component::ok(),
+        Ok(true) => Component::fail("Pending migrations"),
+        Err(e) => {
+            error!("Readiness migrations error: {}", e);
+            Component::fail(UNAVAILABLE)
+        }
+    };
+
+    (database, migrations)
+}
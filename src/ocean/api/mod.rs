@@ -1,7 +1,14 @@
 pub mod authorizer;
+pub mod compression;
+pub mod cors;
+pub mod discover;
 pub mod error;
+pub mod health;
+pub mod proxy;
+pub mod rate_limiter;
+pub mod request_log;
 pub mod router;
 pub mod server;
 pub mod user_cache;
 
-pub use error::{make_error, make_error_data, Error};
+pub use error::{Error, make_error, make_error_data};
@@ -0,0 +1,285 @@
+use crate::config;
+use hyper::{HeaderMap, header};
+use serde::{Deserialize, Deserializer};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+// Network in CIDR notation, e.g. `10.0.0.0/8` or `::1/128`. Single address means full mask.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a, Some(p)),
+            None => (s, None),
+        };
+
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid network address: {}", s))?;
+
+        let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max_prefix)
+                .ok_or_else(|| format!("invalid network prefix: {}", s))?,
+            None => max_prefix,
+        };
+
+        Ok(Cidr { addr, prefix })
+    }
+}
+
+impl fmt::Display for Cidr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+impl<'de> Deserialize<'de> for Cidr {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// Real client address for requests that came through trusted reverse proxies.
+// Forwarding chain is walked from the nearest hop and the first untrusted address is taken,
+// so a client can't spoof its address by sending the header itself.
+pub fn client_ip(headers: &HeaderMap, peer: IpAddr) -> IpAddr {
+    chain_client(
+        &forwarded_chain(headers),
+        peer,
+        &config::CONFIG.server.trusted_proxies,
+    )
+}
+
+// Hop that is unknown or obfuscated hides everything before it, so the trusted proxy
+// that reported it is the farthest known address
+fn chain_client(chain: &[Option<IpAddr>], peer: IpAddr, trusted: &[Cidr]) -> IpAddr {
+    let is_trusted = |ip: &IpAddr| trusted.iter().any(|cidr| cidr.contains(ip));
+    let mut client = peer;
+
+    if !is_trusted(&client) {
+        return client;
+    }
+
+    for node in chain.iter().rev() {
+        match node {
+            Some(ip) => client = *ip,
+            None => return client,
+        }
+
+        if !is_trusted(&client) {
+            return client;
+        }
+    }
+
+    client
+}
+
+// `Forwarded` header has priority over non-standard `X-Forwarded-For`.
+// Nodes that are not addresses are kept as None.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let forwarded: Vec<Option<IpAddr>> = headers
+        .get_all(header::FORWARDED)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.trim()
+                    .eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value))
+            })
+        })
+        .collect();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(parse_node)
+        .collect()
+}
+
+// Node can be `1.2.3.4`, `1.2.3.4:80`, `"[2001:db8::1]:4711"` or `2001:db8::1`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+
+    if let Some(rest) = node.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+
+    node.split_once(':')?.0.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn headers(list: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        for (name, value) in list {
+            headers.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    fn client(list: &[(&str, &str)], peer: &str) -> IpAddr {
+        let trusted: Vec<Cidr> = ["10.0.0.0/8", "::1"]
+            .iter()
+            .map(|c| c.parse().unwrap())
+            .collect();
+
+        chain_client(&forwarded_chain(&headers(list)), ip(peer), &trusted)
+    }
+
+    #[test]
+    fn untrusted_peer_is_client() {
+        assert_eq!(
+            client(&[("x-forwarded-for", "1.1.1.1")], "8.8.8.8"),
+            ip("8.8.8.8")
+        );
+        assert_eq!(client(&[], "10.0.0.1"), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn forwarded_has_priority() {
+        let list = [
+            ("x-forwarded-for", "2.2.2.2"),
+            ("forwarded", "for=1.1.1.1;proto=https"),
+        ];
+        assert_eq!(client(&list, "10.0.0.1"), ip("1.1.1.1"));
+    }
+
+    #[test]
+    fn chain_is_walked_past_trusted_hops() {
+        let list = [("x-forwarded-for", "1.1.1.1, 2.2.2.2, 10.0.0.2")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("2.2.2.2"));
+
+        // Several headers make one chain
+        let list = [
+            ("forwarded", "for=1.1.1.1"),
+            ("forwarded", "For=2.2.2.2, for=10.0.0.2"),
+        ];
+        assert_eq!(client(&list, "10.0.0.1"), ip("2.2.2.2"));
+
+        // Only proxies in chain
+        let list = [("x-forwarded-for", "10.0.0.3, 10.0.0.2")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("10.0.0.3"));
+    }
+
+    #[test]
+    fn spoofed_leftmost_is_ignored() {
+        let list = [("x-forwarded-for", "10.0.0.5, 3.3.3.3")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("3.3.3.3"));
+    }
+
+    #[test]
+    fn node_forms() {
+        assert_eq!(parse_node(" 1.2.3.4 "), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node("1.2.3.4:80"), Some(ip("1.2.3.4")));
+        assert_eq!(parse_node("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(
+            parse_node("\"[2001:db8::1]:4711\""),
+            Some(ip("2001:db8::1"))
+        );
+        assert_eq!(parse_node("\"[2001:db8::1]\""), Some(ip("2001:db8::1")));
+        assert_eq!(parse_node("unknown"), None);
+        assert_eq!(parse_node("_hidden"), None);
+
+        let list = [("forwarded", "for=\"[2001:db8::1]:4711\"")];
+        assert_eq!(client(&list, "::1"), ip("2001:db8::1"));
+    }
+
+    #[test]
+    fn unknown_node_hides_what_is_before_it() {
+        let list = [("forwarded", "for=1.1.1.1, for=unknown, for=10.0.0.2")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("10.0.0.2"));
+
+        let list = [("forwarded", "for=1.1.1.1, for=_hidden")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("10.0.0.1"));
+
+        let list = [("x-forwarded-for", "1.1.1.1, garbage")];
+        assert_eq!(client(&list, "10.0.0.1"), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn cidr_parsing() {
+        let net: Cidr = "192.168.0.0/16".parse().unwrap();
+        assert!(net.contains(&ip("192.168.10.1")));
+        assert!(!net.contains(&ip("192.169.0.1")));
+
+        // IPv4 mapped IPv6 address belongs to IPv4 network
+        assert!(net.contains(&ip("::ffff:192.168.1.1")));
+
+        let single: Cidr = "::1".parse().unwrap();
+        assert_eq!(single.to_string(), "::1/128");
+        assert!(single.contains(&ip("::1")));
+
+        let all: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(all.contains(&ip("8.8.8.8")));
+        assert!(!all.contains(&ip("2001:db8::1")));
+
+        for invalid in [
+            "10.0.0.0/33",
+            "::/129",
+            "10.0.0.0/-1",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "host",
+        ] {
+            assert!(invalid.parse::<Cidr>().is_err(), "{}", invalid);
+        }
+    }
+}
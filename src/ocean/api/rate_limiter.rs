@@ -0,0 +1,243 @@
+use crate::config;
+use crate::types;
+use crate::worker::Worker;
+use log::warn;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{LazyLock, Mutex};
+use std::time::{self, Instant};
+
+const SWEEP_INTERVAL: u64 = 60; // seconds
+const UNKNOWN_PERIOD: u64 = 60; // seconds
+
+// Bucket name of unknown tokens, method names never contain spaces
+const UNKNOWN: &str = "unknown token";
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+enum Key {
+    User(types::Id),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    period: u64, // seconds to refill
+}
+
+type Buckets = HashMap<(String, Key), Bucket>;
+
+static BUCKETS: LazyLock<Mutex<Buckets>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Token bucket per method for every user and every client address. A call must fit
+// into both of them, so neither a single token nor a single address can flood.
+// Returns number of seconds to wait before retry if the call is over budget.
+pub fn check(method: &str, user: &types::User, ip: IpAddr) -> Result<(), u64> {
+    let Some(limit) = config::CONFIG.server.rate_limit.get(method) else {
+        return Ok(());
+    };
+
+    let Some(capacity) = limit.budget(&user.code) else {
+        return Ok(());
+    };
+
+    let keys = [
+        (method.to_string(), Key::User(user.id)),
+        (method.to_string(), Key::Ip(ip)),
+    ];
+
+    let mut buckets = BUCKETS.lock().unwrap();
+
+    take(&mut buckets, &keys, capacity, limit.period, Instant::now()).inspect_err(|retry_after| {
+        warn!(
+            "Rate limit exceeded: method: {}, user: {}, IP: {}, retry after {} s",
+            method, user.id, ip, retry_after
+        )
+    })
+}
+
+// Tokens absent in cache are looked up in database before the method is known. Only
+// tokens not found there are charged, so addresses shared by many users are not limited,
+// but guessing tokens from one address is.
+pub fn check_unknown(ip: IpAddr) -> Result<(), u64> {
+    let capacity = config::CONFIG.user_cache.ip_unknown_tokens;
+    let keys = [(UNKNOWN.to_string(), Key::Ip(ip))];
+    let mut buckets = BUCKETS.lock().unwrap();
+
+    refill(
+        &mut buckets,
+        &keys,
+        capacity,
+        UNKNOWN_PERIOD,
+        Instant::now(),
+    )
+    .inspect_err(|retry_after| {
+        warn!(
+            "Unknown token limit exceeded: IP: {}, retry after {} s",
+            ip, retry_after
+        )
+    })
+}
+
+pub fn charge_unknown(ip: IpAddr) {
+    let capacity = config::CONFIG.user_cache.ip_unknown_tokens;
+    let keys = [(UNKNOWN.to_string(), Key::Ip(ip))];
+    let mut buckets = BUCKETS.lock().unwrap();
+
+    let _ = take(
+        &mut buckets,
+        &keys,
+        capacity,
+        UNKNOWN_PERIOD,
+        Instant::now(),
+    );
+}
+
+// Tokens earned since last update are added to every bucket, returns seconds to wait
+// if any of them has no token for the call
+fn refill(
+    buckets: &mut Buckets,
+    keys: &[(String, Key)],
+    capacity: u32,
+    period: u64,
+    now: Instant,
+) -> Result<(), u64> {
+    let capacity = capacity as f64;
+    let rate = capacity / period as f64; // tokens per second
+    let mut wait: f64 = 0.0;
+
+    for key in keys.iter() {
+        let bucket = buckets.entry(key.clone()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+            period,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            wait = wait.max((1.0 - bucket.tokens) / rate);
+        }
+    }
+
+    if wait > 0.0 {
+        return Err(wait.ceil() as u64);
+    }
+
+    Ok(())
+}
+
+// Call is charged to all buckets only if it fits into each of them
+fn take(
+    buckets: &mut Buckets,
+    keys: &[(String, Key)],
+    capacity: u32,
+    period: u64,
+    now: Instant,
+) -> Result<(), u64> {
+    refill(buckets, keys, capacity, period, now)?;
+
+    for key in keys.iter() {
+        if let Some(bucket) = buckets.get_mut(key) {
+            bucket.tokens -= 1.0;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn start() -> Worker {
+    Worker::spawn(
+        "Rate limiter",
+        time::Duration::from_secs(SWEEP_INTERVAL),
+        false,
+        || sweep(&mut BUCKETS.lock().unwrap(), Instant::now()),
+    )
+}
+
+// Removes buckets that would be full by now, they are equal to absent ones
+fn sweep(buckets: &mut Buckets, now: Instant) {
+    buckets.retain(|_, bucket| now.duration_since(bucket.updated).as_secs() < bucket.period);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn keys(user: types::Id, ip: &str) -> [(String, Key); 2] {
+        [
+            ("feed.getAll".to_string(), Key::User(user)),
+            ("feed.getAll".to_string(), Key::Ip(ip.parse().unwrap())),
+        ]
+    }
+
+    #[test]
+    fn budget_is_spent_and_refilled() {
+        let mut buckets = Buckets::new();
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert_eq!(take(&mut buckets, &keys(1, "10.0.0.1"), 3, 60, now), Ok(()));
+        }
+
+        // One token is earned in 20 seconds
+        assert_eq!(
+            take(&mut buckets, &keys(1, "10.0.0.1"), 3, 60, now),
+            Err(20)
+        );
+
+        let later = now + Duration::from_secs(20);
+        assert_eq!(
+            take(&mut buckets, &keys(1, "10.0.0.1"), 3, 60, later),
+            Ok(())
+        );
+        assert!(take(&mut buckets, &keys(1, "10.0.0.1"), 3, 60, later).is_err());
+    }
+
+    #[test]
+    fn call_fits_into_user_and_address() {
+        let mut buckets = Buckets::new();
+        let now = Instant::now();
+
+        assert_eq!(take(&mut buckets, &keys(1, "10.0.0.1"), 1, 60, now), Ok(()));
+
+        // Same user from other address and other user from same address
+        assert!(take(&mut buckets, &keys(1, "10.0.0.2"), 1, 60, now).is_err());
+        assert!(take(&mut buckets, &keys(2, "10.0.0.1"), 1, 60, now).is_err());
+
+        // Rejected calls are not charged
+        let user = [keys(2, "10.0.0.2")[0].clone()];
+        assert_eq!(take(&mut buckets, &user, 1, 60, now), Ok(()));
+    }
+
+    #[test]
+    fn refill_does_not_charge() {
+        let mut buckets = Buckets::new();
+        let now = Instant::now();
+        let keys = [(UNKNOWN.to_string(), Key::Ip("::1".parse().unwrap()))];
+
+        for _ in 0..5 {
+            assert_eq!(refill(&mut buckets, &keys, 1, 60, now), Ok(()));
+        }
+
+        assert_eq!(take(&mut buckets, &keys, 1, 60, now), Ok(()));
+        assert_eq!(refill(&mut buckets, &keys, 1, 60, now), Err(60));
+    }
+
+    #[test]
+    fn full_buckets_are_swept() {
+        let mut buckets = Buckets::new();
+        let now = Instant::now();
+
+        take(&mut buckets, &keys(1, "10.0.0.1"), 3, 60, now).unwrap();
+        take(&mut buckets, &keys(2, "10.0.0.2"), 3, 10, now).unwrap();
+
+        sweep(&mut buckets, now + Duration::from_secs(10));
+
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains_key(&keys(2, "10.0.0.2")[0]));
+    }
+}
@@ -0,0 +1,111 @@
+use crate::config;
+use crate::config::BodyLog;
+use hyper::header::{HeaderMap, HeaderName};
+use serde_json::Value;
+use std::process;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// Distinguishes ids of different instances and restarts
+static PROCESS_TAG: LazyLock<u32> = LazyLock::new(|| {
+    let nanos = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    nanos ^ process::id().rotate_left(16)
+});
+
+// Id from reverse proxy is kept to correlate logs, otherwise a new one is generated
+pub fn request_id(headers: &HeaderMap) -> String {
+    let incoming = headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+
+    match incoming {
+        Some(id) => id.to_string(),
+        None => format!(
+            "{:08x}-{:x}",
+            *PROCESS_TAG,
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ),
+    }
+}
+
+// Policy for both request and response bodies is chosen by methods of request
+pub fn body_policy(request: Option<&Value>) -> BodyLog {
+    let log = &config::CONFIG.log;
+
+    let methods: Vec<&str> = match request {
+        Some(Value::Array(list)) => list.iter().filter_map(method).collect(),
+        Some(value) => method(value).into_iter().collect(),
+        None => Vec::new(),
+    };
+
+    log.body_policy(methods.into_iter())
+}
+
+pub fn body(raw: &str, policy: BodyLog) -> String {
+    match policy {
+        BodyLog::Full => redact(raw),
+        BodyLog::Truncate => {
+            let body = redact(raw);
+            let max = config::CONFIG.log.max_body_size;
+
+            match body.char_indices().nth(max) {
+                Some((end, _)) => format!("{}...", &body[..end]),
+                None => body,
+            }
+        }
+        BodyLog::Redact => format!("<{} bytes>", raw.len()),
+    }
+}
+
+fn method(value: &Value) -> Option<&str> {
+    value.get("method").and_then(Value::as_str)
+}
+
+pub fn mask_token(token: &str) -> String {
+    const VISIBLE_CHARS: usize = 4;
+    let visible: String = token.chars().take(VISIBLE_CHARS).collect();
+    format!("{}***", visible)
+}
+
+// Hides values of all `token` fields in JSON text before writing it to log
+fn redact(raw: &str) -> String {
+    fn redact_value(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (key, v) in map.iter_mut() {
+                    if key == "token" {
+                        if let Value::String(t) = v {
+                            *t = mask_token(t);
+                        }
+                    } else {
+                        redact_value(v);
+                    }
+                }
+            }
+            Value::Array(list) => list.iter_mut().for_each(redact_value),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<Value>(raw) {
+        Ok(mut value) => {
+            redact_value(&mut value);
+            value.to_string()
+        }
+        Err(_) => raw.to_string(),
+    }
+}
@@ -1,274 +1,531 @@
 use crate::api;
 use crate::api::authorizer;
+use crate::api::authorizer::Access;
+use crate::api::compression;
+use crate::api::cors;
+use crate::api::discover;
+use crate::api::health;
+use crate::api::proxy;
+use crate::api::rate_limiter;
+use crate::api::request_log;
 use crate::api::user_cache;
+use crate::config;
 use crate::controller;
+use crate::controller::ownership::Content;
 use crate::db;
 use crate::json_rpc;
-use crate::types;
-use http_body_util::{BodyExt, Full};
+use crate::metrics;
+use crate::session;
+use futures_util::future;
+use http_body_util::{BodyExt, Full, LengthLimitError, Limited};
 use hyper::body::Buf;
 use hyper::body::Bytes;
+use hyper::header::HeaderValue;
 use hyper::{Method, Request, Response, StatusCode, body::Incoming as IncomingBody, header};
-use log::{error, info};
+use log::{error, info, warn};
+use schemars::JsonSchema;
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::LazyLock;
+use std::time;
 
 type GenericError = Box<dyn std::error::Error + Send + Sync>;
 type Result<T> = std::result::Result<T, GenericError>;
-type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
-type ResponseResult = Result<Response<BoxBody>>;
+pub type BoxBody = http_body_util::combinators::BoxBody<Bytes, hyper::Error>;
+pub type ResponseResult = Result<Response<BoxBody>>;
 
-struct Rh(controller::RequestHandler);
+// Method binds name, access policy, handler and types of its params and result.
+// Types are used to describe API by `rpc.discover`.
+struct RpcMethod {
+    name: &'static str,
+    access: Access,
+    handler: Handler,
+    params: discover::SchemaFn,
+    result: discover::SchemaFn,
+}
 
-static METHODS: LazyLock<HashMap<String, Rh>> = LazyLock::new(|| {
-    let mut m = HashMap::new();
-    m.insert("ping".to_string(), Rh(controller::ping));
-    m.insert(
-        "mandela.create".to_string(),
-        Rh(controller::mandela::create),
-    );
-    m.insert(
-        "mandela.update".to_string(),
-        Rh(controller::mandela::update),
-    );
-    m.insert(
-        "mandela.getOne".to_string(),
-        Rh(controller::mandela::get_one),
-    );
-    m.insert(
-        "mandela.getAll".to_string(),
-        Rh(controller::mandela::get_all),
-    );
-    m.insert(
-        "mandela.delete".to_string(),
-        Rh(controller::mandela::delete),
-    );
-    m.insert("mandela.mark".to_string(), Rh(controller::mandela::mark));
-    m.insert("mandela.vote".to_string(), Rh(controller::mandela::vote));
-    m.insert(
-        "mandela.getVoteUsers".to_string(),
-        Rh(controller::mandela::get_vote_users),
-    );
-    m.insert(
-        "mandela.updateTrash".to_string(),
-        Rh(controller::mandela::update_trash),
-    );
-    m.insert(
-        "user.getNextId".to_string(),
-        Rh(controller::user::get_next_id),
-    );
-    m.insert("user.create".to_string(), Rh(controller::user::create));
-    m.insert("user.delete".to_string(), Rh(controller::user::delete));
-    m.insert("user.auth".to_string(), Rh(controller::user::auth));
-    m.insert("user.logout".to_string(), Rh(controller::user::logout));
-    m.insert("user.getOne".to_string(), Rh(controller::user::get_one));
-    m.insert("user.update".to_string(), Rh(controller::user::update));
-    m.insert(
-        "user.updateToken".to_string(),
-        Rh(controller::user::update_token),
-    );
-    m.insert(
-        "user.updateProfile".to_string(),
-        Rh(controller::user::update_profile),
-    );
-    m.insert(
-        "comment.create".to_string(),
-        Rh(controller::comment::create),
-    );
-    m.insert(
-        "comment.getAll".to_string(),
-        Rh(controller::comment::get_all),
-    );
-    m.insert(
-        "comment.update".to_string(),
-        Rh(controller::comment::update),
-    );
-    m.insert(
-        "comment.delete".to_string(),
-        Rh(controller::comment::delete),
-    );
-    m.insert("like.create".to_string(), Rh(controller::like::create));
-    m.insert("like.delete".to_string(), Rh(controller::like::delete));
-    m.insert("like.getUsers".to_string(), Rh(controller::like::get_users));
-    m.insert("search.getAll".to_string(), Rh(controller::search::get_all));
-    m.insert(
-        "rating.getMandels".to_string(),
-        Rh(controller::rating::get_mandels),
-    );
-    m.insert(
-        "rating.getUsers".to_string(),
-        Rh(controller::rating::get_users),
-    );
-    m.insert("forum.getAll".to_string(), Rh(controller::forum::get_all));
-    m.insert("forum.getNew".to_string(), Rh(controller::forum::get_new));
-    m.insert(
-        "forum.category.create".to_string(),
-        Rh(controller::forum::category::create),
-    );
-    m.insert(
-        "forum.category.getOne".to_string(),
-        Rh(controller::forum::category::get_one),
-    );
-    m.insert(
-        "forum.category.update".to_string(),
-        Rh(controller::forum::category::update),
-    );
-    m.insert(
-        "forum.category.delete".to_string(),
-        Rh(controller::forum::category::delete),
-    );
-    m.insert(
-        "forum.section.create".to_string(),
-        Rh(controller::forum::section::create),
-    );
-    m.insert(
-        "forum.section.getAll".to_string(),
-        Rh(controller::forum::section::get_all),
-    );
-    m.insert(
-        "forum.section.getOne".to_string(),
-        Rh(controller::forum::section::get_one),
-    );
-    m.insert(
-        "forum.section.update".to_string(),
-        Rh(controller::forum::section::update),
-    );
-    m.insert(
-        "forum.section.delete".to_string(),
-        Rh(controller::forum::section::delete),
-    );
-    m.insert(
-        "forum.topic.getAll".to_string(),
-        Rh(controller::forum::topic::get_all),
-    );
-    m.insert(
-        "forum.topic.getOne".to_string(),
-        Rh(controller::forum::topic::get_one),
-    );
-    m.insert(
-        "forum.topic.create".to_string(),
-        Rh(controller::forum::topic::create),
-    );
-    m.insert(
-        "forum.topic.update".to_string(),
-        Rh(controller::forum::topic::update),
-    );
-    m.insert(
-        "forum.topic.delete".to_string(),
-        Rh(controller::forum::topic::delete),
-    );
-    m.insert(
-        "forum.topic.vote".to_string(),
-        Rh(controller::forum::topic::vote),
-    );
-    m.insert(
-        "forum.topic.getVoteUsers".to_string(),
-        Rh(controller::forum::topic::get_vote_users),
-    );
-    m.insert(
-        "forum.post.getAll".to_string(),
-        Rh(controller::forum::post::get_all),
-    );
-    m.insert(
-        "forum.post.getOne".to_string(),
-        Rh(controller::forum::post::get_one),
-    );
-    m.insert(
-        "forum.post.create".to_string(),
-        Rh(controller::forum::post::create),
-    );
-    m.insert(
-        "forum.post.update".to_string(),
-        Rh(controller::forum::post::update),
-    );
-    m.insert(
-        "forum.post.delete".to_string(),
-        Rh(controller::forum::post::delete),
-    );
-    m.insert(
-        "activity.getAll".to_string(),
-        Rh(controller::activity::get_all),
-    );
-    m.insert("feed.getAll".to_string(), Rh(controller::feed::get_all));
-    m
+enum Handler {
+    Db(controller::RequestHandler),
+    Static(fn() -> serde_json::Value), // Needs no database connection
+}
+
+impl RpcMethod {
+    // Types are taken from signature of typed handler, so they can't diverge from it
+    const fn new<P: JsonSchema, R: JsonSchema>(
+        name: &'static str,
+        access: Access,
+        handler: controller::RequestHandler,
+        _typed: fn(controller::RequestData, P) -> controller::RequestResult<R>,
+    ) -> Self {
+        RpcMethod {
+            name,
+            access,
+            handler: Handler::Db(handler),
+            params: discover::schema::<P>,
+            result: discover::schema::<R>,
+        }
+    }
+}
+
+macro_rules! method {
+    ($name:literal, $access:expr, $handler:path) => {
+        RpcMethod::new(
+            $name,
+            $access,
+            |data| controller::call(data, $handler),
+            $handler,
+        )
+    };
+}
+
+static METHODS: &[RpcMethod] = &[
+    method!("ping", Access::Anonym, controller::ping),
+    RpcMethod {
+        name: "rpc.discover",
+        access: Access::Anonym,
+        handler: Handler::Static(rpc_discover),
+        params: discover::schema::<()>,
+        result: discover::schema::<serde_json::Value>,
+    },
+    method!(
+        "mandela.create",
+        Access::AnonymIfAllowed,
+        controller::mandela::create
+    ),
+    method!(
+        "mandela.update",
+        Access::Author(Content::Mandela),
+        controller::mandela::update
+    ),
+    method!(
+        "mandela.getOne",
+        Access::Anonym,
+        controller::mandela::get_one
+    ),
+    method!(
+        "mandela.getAll",
+        Access::Anonym,
+        controller::mandela::get_all
+    ),
+    method!("mandela.delete", Access::Admin, controller::mandela::delete),
+    method!("mandela.mark", Access::User, controller::mandela::mark),
+    method!("mandela.vote", Access::User, controller::mandela::vote),
+    method!(
+        "mandela.getVoteUsers",
+        Access::Admin,
+        controller::mandela::get_vote_users
+    ),
+    method!(
+        "mandela.updateTrash",
+        Access::Admin,
+        controller::mandela::update_trash
+    ),
+    method!(
+        "user.getNextId",
+        Access::Anonym,
+        controller::user::get_next_id
+    ),
+    method!("user.create", Access::Anonym, controller::user::create),
+    method!("user.delete", Access::Admin, controller::user::delete),
+    method!("user.auth", Access::Anonym, controller::user::auth),
+    method!("user.logout", Access::User, controller::user::logout),
+    method!("user.getOne", Access::Anonym, controller::user::get_one),
+    method!("user.update", Access::Admin, controller::user::update),
+    method!(
+        "user.updateToken",
+        Access::User,
+        controller::user::update_token
+    ),
+    method!(
+        "user.updateProfile",
+        Access::User,
+        controller::user::update_profile
+    ),
+    method!(
+        "user.getSessions",
+        Access::User,
+        controller::user::get_sessions
+    ),
+    method!(
+        "user.revokeSession",
+        Access::User,
+        controller::user::revoke_session
+    ),
+    method!(
+        "comment.create",
+        Access::AnonymIfAllowed,
+        controller::comment::create
+    ),
+    method!(
+        "comment.getAll",
+        Access::Anonym,
+        controller::comment::get_all
+    ),
+    method!(
+        "comment.update",
+        Access::Author(Content::Comment),
+        controller::comment::update
+    ),
+    method!(
+        "comment.delete",
+        Access::Author(Content::Comment),
+        controller::comment::delete
+    ),
+    method!("like.create", Access::User, controller::like::create),
+    method!("like.delete", Access::User, controller::like::delete),
+    method!("like.getUsers", Access::Admin, controller::like::get_users),
+    method!("search.getAll", Access::Anonym, controller::search::get_all),
+    method!(
+        "rating.getMandels",
+        Access::Anonym,
+        controller::rating::get_mandels
+    ),
+    method!(
+        "rating.getUsers",
+        Access::Anonym,
+        controller::rating::get_users
+    ),
+    method!("forum.getAll", Access::Anonym, controller::forum::get_all),
+    method!("forum.getNew", Access::Anonym, controller::forum::get_new),
+    method!(
+        "forum.category.create",
+        Access::Admin,
+        controller::forum::category::create
+    ),
+    method!(
+        "forum.category.getOne",
+        Access::Anonym,
+        controller::forum::category::get_one
+    ),
+    method!(
+        "forum.category.update",
+        Access::Admin,
+        controller::forum::category::update
+    ),
+    method!(
+        "forum.category.delete",
+        Access::Admin,
+        controller::forum::category::delete
+    ),
+    method!(
+        "forum.section.create",
+        Access::Admin,
+        controller::forum::section::create
+    ),
+    method!(
+        "forum.section.getAll",
+        Access::Anonym,
+        controller::forum::section::get_all
+    ),
+    method!(
+        "forum.section.getOne",
+        Access::Anonym,
+        controller::forum::section::get_one
+    ),
+    method!(
+        "forum.section.update",
+        Access::Admin,
+        controller::forum::section::update
+    ),
+    method!(
+        "forum.section.delete",
+        Access::Admin,
+        controller::forum::section::delete
+    ),
+    method!(
+        "forum.topic.getAll",
+        Access::Anonym,
+        controller::forum::topic::get_all
+    ),
+    method!(
+        "forum.topic.getOne",
+        Access::Anonym,
+        controller::forum::topic::get_one
+    ),
+    method!(
+        "forum.topic.create",
+        Access::AnonymIfAllowed,
+        controller::forum::topic::create
+    ),
+    method!(
+        "forum.topic.update",
+        Access::Author(Content::ForumTopic),
+        controller::forum::topic::update
+    ),
+    method!(
+        "forum.topic.delete",
+        Access::Author(Content::ForumTopic),
+        controller::forum::topic::delete
+    ),
+    method!(
+        "forum.topic.vote",
+        Access::User,
+        controller::forum::topic::vote
+    ),
+    method!(
+        "forum.topic.getVoteUsers",
+        Access::Admin,
+        controller::forum::topic::get_vote_users
+    ),
+    method!(
+        "forum.post.getAll",
+        Access::Anonym,
+        controller::forum::post::get_all
+    ),
+    method!(
+        "forum.post.getOne",
+        Access::Anonym,
+        controller::forum::post::get_one
+    ),
+    method!(
+        "forum.post.create",
+        Access::AnonymIfAllowed,
+        controller::forum::post::create
+    ),
+    method!(
+        "forum.post.update",
+        Access::Author(Content::ForumPost),
+        controller::forum::post::update
+    ),
+    method!(
+        "forum.post.delete",
+        Access::Author(Content::ForumPost),
+        controller::forum::post::delete
+    ),
+    method!(
+        "activity.getAll",
+        Access::Anonym,
+        controller::activity::get_all
+    ),
+    method!("feed.getAll", Access::Anonym, controller::feed::get_all),
+];
+
+static REGISTRY: LazyLock<HashMap<&'static str, &'static RpcMethod>> = LazyLock::new(|| {
+    let mut registry = HashMap::new();
+
+    for method in METHODS {
+        if registry.insert(method.name, method).is_some() {
+            panic!("Method {} is registered twice", method.name);
+        }
+    }
+
+    registry
 });
 
+// Checks method registry at startup instead of the first request
+pub fn init() {
+    LazyLock::force(&REGISTRY);
+}
+
+// rpc.discover
+fn rpc_discover() -> serde_json::Value {
+    let methods = METHODS
+        .iter()
+        .map(|m| discover::Method {
+            name: m.name,
+            params: m.params,
+            result: m.result,
+            user_code: m.access.user_code(),
+        })
+        .collect();
+
+    discover::document(methods)
+}
+
 pub async fn route(req: Request<IncomingBody>, addr: SocketAddr) -> ResponseResult {
-    if req.method() != Method::POST || req.uri().path() != "/api" {
-        return bad_request(req);
+    if req.method() == Method::GET {
+        match req.uri().path() {
+            "/health" => return status(health::live()),
+            "/ready" => return status(health::ready().await),
+            "/metrics" => {
+                // Internal counters are not public, even when requested through proxy
+                let ip = proxy::client_ip(req.headers(), addr.ip());
+                let allowed = &config::CONFIG.server.metrics_allowed;
+
+                if !allowed.iter().any(|cidr| cidr.contains(&ip)) {
+                    return forbidden(ip);
+                }
+
+                return Ok(Response::builder()
+                    .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+                    .body(full(metrics::render()))
+                    .unwrap());
+            }
+            _ => {}
+        }
     }
 
-    let query;
-    if let Some(q) = req.uri().query() {
-        query = q;
+    let origin = cors::allowed_origin(req.headers());
+    let request_id = request_log::request_id(req.headers());
+
+    let mut response = if req.method() == Method::OPTIONS && req.uri().path() == "/api" {
+        cors::preflight(full(""))
     } else {
-        return bad_request(req);
+        handle(req, addr, &request_id).await?
     };
 
-    let url_params = url::form_urlencoded::parse(query.as_bytes());
-    let hash_params: HashMap<_, _> = url_params.into_owned().collect();
+    cors::apply(origin, response.headers_mut());
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response
+            .headers_mut()
+            .insert(request_log::REQUEST_ID_HEADER, value);
+    }
+
+    Ok(response)
+}
+
+async fn handle(req: Request<IncomingBody>, addr: SocketAddr, request_id: &str) -> ResponseResult {
+    if req.method() != Method::POST || req.uri().path() != "/api" {
+        return bad_request(req, request_id);
+    }
 
     let token;
 
-    if let Some(t) = hash_params.get("token") {
+    if let Some(t) = request_token(&req) {
         token = t;
     } else {
-        return bad_request(req);
+        return bad_request(req, request_id);
     }
 
-    let user;
+    let client_ip = proxy::client_ip(req.headers(), addr.ip());
 
-    if let Some(u) = user_cache::get(token) {
-        user = u;
-    } else {
-        return unauthorized(token);
+    // Unknown tokens and addresses over their budget of unknown tokens don't reach database
+    let session = match user_cache::lookup(&token) {
+        user_cache::Lookup::Found(s) => s,
+        user_cache::Lookup::Unknown => return unauthorized(&token, request_id),
+        user_cache::Lookup::Miss => {
+            if let Err(retry_after) = rate_limiter::check_unknown(client_ip) {
+                return too_many_requests(retry_after, request_id);
+            }
+
+            match load_session(token.clone()).await {
+                Ok(Some(s)) => s,
+                Ok(None) => {
+                    rate_limiter::charge_unknown(client_ip);
+                    return unauthorized(&token, request_id);
+                }
+                Err(e) => return service_unavailable(&e, request_id),
+            }
+        }
+    };
+
+    session::touch(session.id);
+
+    let user_id = session.user.id;
+    let user_name = session.user.name.clone();
+    let encoding = compression::negotiate(req.headers());
+    let limits = &config::CONFIG.server.limits;
+
+    let content_length = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+
+    if content_length.is_some_and(|len| len > limits.max_body_size) {
+        return payload_too_large(content_length, request_id);
     }
 
-    let user_id = user.id;
-    let user_name = user.name.clone();
-    let whole_body = req.collect().await?.aggregate();
+    let body = Limited::new(req.into_body(), limits.max_body_size);
+    let body_timeout = time::Duration::from_secs(limits.body_timeout);
+
+    let whole_body = match tokio::time::timeout(body_timeout, body.collect()).await {
+        Ok(Ok(b)) => b.aggregate(),
+        Ok(Err(e)) if e.is::<LengthLimitError>() => return payload_too_large(None, request_id),
+        Ok(Err(e)) => return Err(e),
+        Err(_) => return request_timeout(client_ip, request_id),
+    };
     let bytes = whole_body.chunk();
-    let raw_req = String::from_utf8_lossy(bytes);
+    let value = serde_json::from_slice::<serde_json::Value>(bytes);
+    let body_policy = request_log::body_policy(value.as_ref().ok());
 
     info!(
-        "[REQUEST] {} ({}: {}) {}",
-        addr.ip(),
+        request_id,
+        ip:% = client_ip,
         user_id,
-        user.name,
-        raw_req
+        user_name = user_name.as_str(),
+        body:% = request_log::body(&String::from_utf8_lossy(bytes), body_policy);
+        "[REQUEST]"
     );
 
-    let json_rpc_req = serde_json::from_slice::<json_rpc::Request>(bytes);
-
-    let json_rpc_resp = if let Ok(r) = json_rpc_req {
-        exec(user, r)
-    } else {
-        json_rpc::response::Response {
-            error: Some(json_rpc::Error::from_api_error(&api::Error::new(
-                api::error::PARSE_ERROR,
-                Some(json_rpc_req.err().unwrap().to_string()),
-            ))),
-            ..Default::default()
+    let raw_resp = match value {
+        Ok(serde_json::Value::Array(list)) => {
+            exec_batch(session, client_ip, list, request_id).await
         }
+        Ok(value) => match parse_request(value, json_rpc::Version::Legacy) {
+            Ok(r) if r.version() == json_rpc::Version::V2 && r.id.is_none() => {
+                exec_measured(session, client_ip, r, request_id).await;
+                String::new()
+            }
+            Ok(r) => {
+                let resp = exec_measured(session, client_ip, r, request_id).await;
+                serde_json::to_string(&resp).unwrap()
+            }
+            Err(resp) => serde_json::to_string(&resp).unwrap(),
+        },
+        Err(e) => serde_json::to_string(&transport_error(
+            json_rpc::request::raw_version_of(bytes),
+            api::error::PARSE_ERROR,
+            e.to_string(),
+        ))
+        .unwrap(),
     };
 
-    let raw_resp = serde_json::to_string(&json_rpc_resp).unwrap();
     info!(
-        "[RESPONSE] {} ({}: {}) {}",
-        addr.ip(),
+        request_id,
+        ip:% = client_ip,
         user_id,
-        user_name,
-        raw_resp
+        user_name = user_name.as_str(),
+        body:% = request_log::body(&raw_resp, body_policy);
+        "[RESPONSE]"
     );
 
-    let mut response = Response::builder().body(full(raw_resp)).unwrap();
-    response.headers_mut().insert(
-        "Access-Control-Allow-Origin",
-        header::HeaderValue::from_static("*"),
-    );
+    let mut response = Response::builder();
 
-    Ok(response)
+    if let Some(e) = encoding {
+        response = response.header(header::VARY, "Accept-Encoding");
+
+        match compression::compress(e, raw_resp.as_bytes()) {
+            Ok(Some(compressed)) => {
+                let mut response = response.body(full(compressed)).unwrap();
+                compression::apply(e, response.headers_mut());
+                return Ok(response);
+            }
+            Ok(None) => {}
+            Err(err) => error!(request_id; "Response compression error: {}", err),
+        }
+    }
+
+    Ok(response.body(full(raw_resp)).unwrap())
+}
+
+// Token from `Authorization: Bearer` header has priority over deprecated `token` query parameter
+fn request_token(req: &Request<IncomingBody>) -> Option<String> {
+    if let Some(value) = req.headers().get(header::AUTHORIZATION) {
+        return value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim_start().split_once(' '))
+            // Authentication scheme is case-insensitive
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, t)| t.trim().to_string())
+            .filter(|t| !t.is_empty());
+    }
+
+    let query = req.uri().query()?;
+    let url_params = url::form_urlencoded::parse(query.as_bytes());
+    let mut hash_params: HashMap<_, _> = url_params.into_owned().collect();
+    hash_params.remove("token").filter(|t| !t.is_empty())
+}
+
+// Session absent in cache is loaded from database on the blocking thread pool
+async fn load_session(token: String) -> Result<Option<user_cache::Session>> {
+    tokio::task::spawn_blocking(move || -> Result<_> {
+        let mut db = db::Db::new()?;
+        Ok(user_cache::load(&mut db.conn, &token)?)
+    })
+    .await?
 }
 
 fn full<T: Into<Bytes>>(chunk: T) -> BoxBody {
@@ -277,8 +534,18 @@ fn full<T: Into<Bytes>>(chunk: T) -> BoxBody {
         .boxed()
 }
 
-fn bad_request(req: Request<IncomingBody>) -> ResponseResult {
+fn status((code, body): (StatusCode, String)) -> ResponseResult {
+    Ok(Response::builder()
+        .status(code)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(full(body))
+        .unwrap())
+}
+
+fn bad_request(req: Request<IncomingBody>, request_id: &str) -> ResponseResult {
     info!(
+        request_id;
         "Bad request: method: {}, URL: {}",
         req.method().as_str(),
         req.uri().path()
@@ -290,8 +557,36 @@ fn bad_request(req: Request<IncomingBody>) -> ResponseResult {
         .unwrap())
 }
 
-fn unauthorized(token: &str) -> ResponseResult {
-    info!("Unauthorized: token: {}", token);
+fn forbidden(ip: IpAddr) -> ResponseResult {
+    info!("Forbidden: metrics requested from {}", ip);
+
+    Ok(Response::builder()
+        .status(StatusCode::FORBIDDEN)
+        .body(full("Forbidden"))
+        .unwrap())
+}
+
+fn payload_too_large(content_length: Option<usize>, request_id: &str) -> ResponseResult {
+    info!(request_id; "Payload too large: content length: {:?}", content_length);
+
+    Ok(Response::builder()
+        .status(StatusCode::PAYLOAD_TOO_LARGE)
+        .body(full("Payload too large"))
+        .unwrap())
+}
+
+fn request_timeout(ip: IpAddr, request_id: &str) -> ResponseResult {
+    info!(request_id; "Request timeout: body is not received from {}", ip);
+
+    Ok(Response::builder()
+        .status(StatusCode::REQUEST_TIMEOUT)
+        .header(header::CONNECTION, "close")
+        .body(full("Request timeout"))
+        .unwrap())
+}
+
+fn unauthorized(token: &str, request_id: &str) -> ResponseResult {
+    info!(request_id; "Unauthorized: token: {}", request_log::mask_token(token));
 
     Ok(Response::builder()
         .status(StatusCode::UNAUTHORIZED)
@@ -299,17 +594,239 @@ fn unauthorized(token: &str) -> ResponseResult {
         .unwrap())
 }
 
-fn exec(user: types::User, req: json_rpc::Request) -> json_rpc::Response {
-    let mut resp = json_rpc::Response::default();
+fn too_many_requests(retry_after: u64, request_id: &str) -> ResponseResult {
+    info!(request_id; "Too many requests: retry after {} s", retry_after);
+
+    Ok(Response::builder()
+        .status(StatusCode::TOO_MANY_REQUESTS)
+        .header(header::RETRY_AFTER, retry_after)
+        .body(full("Too many requests"))
+        .unwrap())
+}
+
+fn service_unavailable(error: &GenericError, request_id: &str) -> ResponseResult {
+    error!(request_id; "Session is not loaded: {}", error);
+
+    Ok(Response::builder()
+        .status(StatusCode::SERVICE_UNAVAILABLE)
+        .body(full("Service unavailable"))
+        .unwrap())
+}
+
+// Errors of the whole body have no request id. Legacy clients get them in their own form
+// unless the body shows that the client uses specification.
+fn transport_error(
+    version: json_rpc::Version,
+    code: api::error::ErrorCode,
+    data: String,
+) -> json_rpc::Response {
+    json_rpc::Response {
+        version,
+        error: Some(json_rpc::Error::from_api_error(&api::Error::new(
+            code,
+            Some(data),
+        ))),
+        ..Default::default()
+    }
+}
+
+// Request with `jsonrpc` member that can't be deserialized is answered according
+// to specification as invalid request, otherwise with project parse error.
+// Value that is not an object is answered in the version of its batch.
+fn parse_request(
+    value: serde_json::Value,
+    batch_version: json_rpc::Version,
+) -> std::result::Result<json_rpc::Request, Box<json_rpc::Response>> {
+    if !value.is_object() {
+        return Err(Box::new(transport_error(
+            batch_version,
+            api::error::INVALID_REQUEST,
+            "Request must be an object".to_string(),
+        )));
+    }
+
+    let version = json_rpc::request::version_of(&value);
+    let id = value
+        .get("id")
+        .and_then(|id| serde_json::from_value::<json_rpc::Id>(id.clone()).ok());
+
+    serde_json::from_value::<json_rpc::Request>(value).map_err(|e| match version {
+        json_rpc::Version::Legacy => Box::new(transport_error(
+            version,
+            api::error::PARSE_ERROR,
+            e.to_string(),
+        )),
+        json_rpc::Version::V2 => Box::new(json_rpc::Response {
+            version,
+            id,
+            error: Some(json_rpc::Error::from_api_error(&api::Error::new(
+                api::error::INVALID_REQUEST,
+                Some(e.to_string()),
+            ))),
+            ..Default::default()
+        }),
+    })
+}
+
+// Every batch element is executed independently. Elements without id are notifications
+// and produce no response, so a batch of notifications only gives an empty body.
+async fn exec_batch(
+    session: user_cache::Session,
+    ip: IpAddr,
+    list: Vec<serde_json::Value>,
+    request_id: &str,
+) -> String {
+    let version = json_rpc::request::batch_version(&list);
+
+    if list.is_empty() {
+        let resp = transport_error(
+            version,
+            api::error::INVALID_REQUEST,
+            "Empty batch".to_string(),
+        );
+        return serde_json::to_string(&resp).unwrap();
+    }
+
+    // Each call takes its own database connection, so large batch could exhaust the pool
+    let max_batch = config::CONFIG.server.limits.max_batch;
 
-    if let Some(id) = req.id {
-        resp.id = id;
+    if list.len() > max_batch {
+        let resp = transport_error(
+            version,
+            api::error::INVALID_REQUEST,
+            format!("Batch exceeds {} calls", max_batch),
+        );
+        return serde_json::to_string(&resp).unwrap();
     }
 
+    let calls = list.into_iter().map(|value| {
+        let session = session.clone();
+
+        async move {
+            match parse_request(value, version) {
+                Ok(r) => {
+                    let notification = r.id.is_none();
+                    let resp = exec_measured(session, ip, r, request_id).await;
+                    (!notification).then_some(resp)
+                }
+                Err(resp) => Some(*resp),
+            }
+        }
+    });
+
+    let responses: Vec<json_rpc::Response> = future::join_all(calls)
+        .await
+        .into_iter()
+        .flatten()
+        .collect();
+
+    if responses.is_empty() {
+        return String::new();
+    }
+
+    serde_json::to_string(&responses).unwrap()
+}
+
+async fn exec_measured(
+    session: user_cache::Session,
+    ip: IpAddr,
+    req: json_rpc::Request,
+    request_id: &str,
+) -> json_rpc::Response {
+    let start = time::Instant::now();
+
+    // Unknown names are grouped to keep the number of metric series bounded
+    let method = if REGISTRY.contains_key(req.method.as_str()) {
+        req.method.clone()
+    } else {
+        "unknown".to_string()
+    };
+
+    let resp = exec_blocking(session, ip, req, request_id).await;
+    let code = resp.error.as_ref().map(|e| e.code).unwrap_or(0);
+    metrics::observe_rpc(&method, code, start.elapsed());
+
+    resp
+}
+
+// Controllers are synchronous, so they are executed on the blocking thread pool to not stall
+// the async runtime. Client of handler that exceeds its timeout gets an error immediately,
+// the handler itself is stopped by statement timeout of its database connection.
+async fn exec_blocking(
+    session: user_cache::Session,
+    ip: IpAddr,
+    req: json_rpc::Request,
+    request_id: &str,
+) -> json_rpc::Response {
+    let mut resp = json_rpc::Response {
+        version: req.version(),
+        id: req.id.clone(),
+        method: req.method.clone(),
+        ..Default::default()
+    };
+
+    let timeout = config::CONFIG.server.timeout.method(&req.method);
+    let deadline = time::Instant::now() + timeout;
+    let id = request_id.to_string();
+    let handle = tokio::task::spawn_blocking(move || exec(session, ip, req, deadline, &id));
+
+    let err = match tokio::time::timeout(timeout, handle).await {
+        Ok(Ok(r)) => return r,
+        Ok(Err(e)) => {
+            error!(request_id, method = resp.method.as_str(); "Method failed: {}", e);
+            api::error::Error::new(
+                api::error::INTERNAL_SERVER_ERROR,
+                Some(request_id.to_string()),
+            )
+        }
+        Err(_) => {
+            warn!(
+                request_id,
+                method = resp.method.as_str();
+                "Method timed out after {:?}",
+                timeout
+            );
+            api::error::Error::new(api::error::EXECUTION_TIMEOUT, None)
+        }
+    };
+
+    resp.error = Some(json_rpc::Error::from_api_error(&err));
+    resp
+}
+
+fn exec(
+    session: user_cache::Session,
+    ip: IpAddr,
+    req: json_rpc::Request,
+    deadline: time::Instant,
+    request_id: &str,
+) -> json_rpc::Response {
+    let mut resp = json_rpc::Response {
+        version: req.version(),
+        id: req.id,
+        ..Default::default()
+    };
+
     let method = req.method;
     resp.method = method.clone();
 
-    if !authorizer::authorize(&method, &user.code) {
+    if let Some(v) = req.jsonrpc
+        && v != json_rpc::request::VERSION
+    {
+        resp.error = Some(json_rpc::Error::from_api_error(&api::Error::new(
+            api::error::INVALID_REQUEST,
+            Some(format!("Unsupported version {}", v)),
+        )));
+        return resp;
+    }
+
+    let Some(func) = REGISTRY.get(method.as_str()) else {
+        let server_err = api::error::Error::new(api::error::METHOD_NOT_FOUND, Some(method));
+        resp.error = Some(json_rpc::Error::from_api_error(&server_err));
+        return resp;
+    };
+
+    if !authorizer::authorize(func.access, &session.user.code) {
         resp.error = Some(json_rpc::Error::from_api_error(&api::Error::new(
             api::error::ACCESS_DENIED,
             None,
@@ -317,7 +834,7 @@ fn exec(user: types::User, req: json_rpc::Request) -> json_rpc::Response {
         return resp;
     }
 
-    if user.blocked && method != "user.logout" {
+    if session.user.blocked && method != "user.logout" {
         resp.error = Some(json_rpc::Error::from_api_error(&api::Error::new(
             api::error::ACCOUNT_BLOCKED,
             None,
@@ -325,31 +842,138 @@ fn exec(user: types::User, req: json_rpc::Request) -> json_rpc::Response {
         return resp;
     }
 
-    match METHODS.get(&method) {
-        Some(func) => {
-            let db = db::Db::new();
-            let data = controller::RequestData::new(db, user, req.params);
-            let result = func.0(data);
-
-            match result {
-                Ok(r) => resp.result = r,
-                Err(e) => {
-                    let api_err = e.downcast_ref::<api::error::Error>();
-                    if let Some(i) = api_err {
-                        resp.error = Some(json_rpc::Error::from_api_error(i));
-                    } else {
-                        error!("{}", e);
-                        let server_err =
-                            api::error::Error::new(api::error::INTERNAL_SERVER_ERROR, None);
-                        resp.error = Some(json_rpc::Error::from_api_error(&server_err));
-                    }
-                }
-            };
+    // Only calls that are going to be executed are charged
+    if let Err(retry_after) = rate_limiter::check(&method, &session.user, ip) {
+        let err = api::error::Error::new(
+            api::error::RATE_LIMIT_EXCEEDED,
+            Some(retry_after.to_string()),
+        );
+        resp.error = Some(json_rpc::Error::from_api_error(&err));
+        return resp;
+    }
+
+    let handler = match func.handler {
+        Handler::Db(h) => h,
+        Handler::Static(h) => {
+            resp.result = Some(h());
+            return resp;
         }
-        None => {
-            let server_err = api::error::Error::new(api::error::METHOD_NOT_FOUND, Some(method));
-            resp.error = Some(json_rpc::Error::from_api_error(&server_err));
+    };
+
+    let timeout = deadline.saturating_duration_since(time::Instant::now());
+
+    let db = match db::Db::with_timeout(timeout) {
+        Ok(db) => db,
+        Err(e) => {
+            error!(request_id; "{}", e);
+            let db_err = api::error::Error::new(api::error::DATABASE_UNAVAILABLE, None);
+            resp.error = Some(json_rpc::Error::from_api_error(&db_err));
+            return resp;
         }
-    }
+    };
+
+    let mut data = controller::RequestData::new(db, session, req.params);
+
+    let result = match func.access {
+        Access::Author(content) => {
+            controller::ownership::check(&mut data, content).and_then(|_| handler(data))
+        }
+        _ => handler(data),
+    };
+
+    match result {
+        Ok(r) => resp.result = r,
+        Err(e) => {
+            if let Some(api_err) = e.api_error() {
+                resp.error = Some(json_rpc::Error::from_api_error(&api_err));
+            } else {
+                error!(request_id, method = method.as_str(); "{}", e);
+                // Client can report request id to find the error in log
+                let server_err = api::error::Error::new(
+                    api::error::INTERNAL_SERVER_ERROR,
+                    Some(request_id.to_string()),
+                );
+                resp.error = Some(json_rpc::Error::from_api_error(&server_err));
+            }
+        }
+    };
+
     resp
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::ownership;
+    use crate::types;
+    use serde_json::json;
+
+    #[test]
+    fn version_of_errors_without_request() {
+        use json_rpc::Version::{Legacy, V2};
+        use json_rpc::request::{batch_version, raw_version_of};
+
+        assert_eq!(raw_version_of(b"{\"method\": "), Legacy);
+        assert_eq!(raw_version_of(b"{\"jsonrpc\": \"2.0\", "), V2);
+
+        assert_eq!(batch_version(&[]), Legacy);
+        assert_eq!(
+            batch_version(&[json!(1), json!({ "method": "ping" })]),
+            Legacy
+        );
+        assert_eq!(batch_version(&[json!(1), json!({ "jsonrpc": "2.0" })]), V2);
+
+        let not_object = |batch| parse_request(json!(1), batch).err().unwrap().version;
+        assert_eq!(not_object(Legacy), Legacy);
+        assert_eq!(not_object(V2), V2);
+
+        let invalid = |value| parse_request(value, V2).err().unwrap().version;
+        assert_eq!(invalid(json!({ "method": 1 })), Legacy);
+        assert_eq!(invalid(json!({ "jsonrpc": "2.0", "method": 1 })), V2);
+    }
+
+    #[test]
+    fn content_is_changed_only_by_author() {
+        let create_ts = chrono::Utc::now().naive_utc();
+        let window = Some(60 * 60);
+        let user = |id| types::User {
+            id,
+            code: types::UserCode::User,
+            name: String::new(),
+            blocked: false,
+        };
+
+        for (method, table) in [
+            ("mandela.update", "mandels"),
+            ("comment.update", "comments"),
+            ("comment.delete", "comments"),
+            ("forum.topic.update", "forum_topics"),
+            ("forum.topic.delete", "forum_topics"),
+            ("forum.post.update", "forum_posts"),
+            ("forum.post.delete", "forum_posts"),
+        ] {
+            let Access::Author(content) = REGISTRY[method].access else {
+                panic!("{}: not restricted to author", method);
+            };
+            assert_eq!(ownership::table(content), table, "{}", method);
+
+            // Author is taken from the record, so only its id matters here
+            let late = create_ts + chrono::TimeDelta::hours(2);
+            assert!(
+                ownership::allowed(&user(1), 1, create_ts, create_ts, window),
+                "{}",
+                method
+            );
+            assert!(
+                !ownership::allowed(&user(2), 1, create_ts, create_ts, window),
+                "{}",
+                method
+            );
+            assert!(
+                !ownership::allowed(&user(1), 1, create_ts, late, window),
+                "{}",
+                method
+            );
+        }
+    }
+}
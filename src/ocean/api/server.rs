@@ -1,15 +1,27 @@
 use super::router;
 use crate::config;
-use hyper::server::conn::http1;
+use crate::metrics;
+use crate::shutdown;
+use hyper::Request;
+use hyper::body::Incoming;
 use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
-use log::info;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto;
+use log::{info, warn};
 use rustls::ServerConfig;
 use rustls::pki_types::pem::PemObject;
 use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
 use std::sync;
-use tokio::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{Notify, watch};
 use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+type GenericError = Box<dyn std::error::Error + Send + Sync>;
 
 pub struct ApiServer;
 
@@ -18,53 +30,66 @@ impl ApiServer {
         Self
     }
 
-    pub async fn listen(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    // Serves connections until `shutdown` completes, then waits for in-flight requests.
+    // Returns false if they are not finished in configured drain timeout.
+    pub async fn listen(&self, shutdown: impl Future<Output = ()>) -> Result<bool, GenericError> {
         let port = config::CONFIG.server.port;
         let addr = format!("0.0.0.0:{}", port);
 
-        let tls_cfg = {
-            let certs = CertificateDer::pem_file_iter(config::CONFIG.server.ssl.cert.as_str())
-                .unwrap()
-                .map(|cert| cert.unwrap())
-                .collect();
-
-            let private_key =
-                PrivateKeyDer::from_pem_file(config::CONFIG.server.ssl.key.as_str()).unwrap();
-
-            let config = ServerConfig::builder()
-                .with_no_client_auth()
-                .with_single_cert(certs, private_key)
-                .expect("Bad certificate or private key");
-            sync::Arc::new(config)
+        let tls_acceptor = match &config::CONFIG.server.ssl {
+            Some(ssl) => Some(TlsAcceptor::from(tls_config(ssl)?)),
+            None => None,
         };
 
-        let tls_acceptor = TlsAcceptor::from(tls_cfg);
         let listener = TcpListener::bind(&addr).await?;
-        info!("API server listen on port {}", port);
+
+        if tls_acceptor.is_some() {
+            info!("API server listen on port {} (HTTPS)", port);
+        } else {
+            info!("API server listen on port {} (HTTP)", port);
+        }
+
+        // Every connection holds a receiver until it is closed
+        let (drain_tx, drain_rx) = watch::channel(false);
+        tokio::pin!(shutdown);
 
         loop {
-            let (stream, addr) = listener.accept().await?;
+            let (stream, addr) = tokio::select! {
+                conn = listener.accept() => conn?,
+                _ = &mut shutdown => break,
+            };
+
             let acceptor = tls_acceptor.clone();
+            let drain = drain_rx.clone();
 
             tokio::task::spawn(async move {
-                let tls_stream = match acceptor.accept(stream).await {
-                    Ok(s) => s,
-                    Err(e) => {
-                        eprintln!("TLS error: {:?}", e);
-                        return;
-                    }
-                };
-
-                let io = TokioIo::new(tls_stream);
+                let _connection = metrics::ConnectionGuard::new();
 
-                if let Err(err) = http1::Builder::new()
-                    .serve_connection(io, service_fn(move |req| router::route(req, addr)))
-                    .await
-                {
-                    eprintln!("Error serving connection: {:?}", err);
+                match acceptor {
+                    Some(acceptor) => {
+                        if let Some(tls_stream) = handshake(acceptor, stream, addr).await {
+                            serve(tls_stream, addr, drain).await;
+                        }
+                    }
+                    None => serve(stream, addr, drain).await,
                 }
             });
         }
+
+        drop(listener);
+        drop(drain_rx);
+
+        info!(
+            "API server stopped, draining {} connections",
+            drain_tx.receiver_count()
+        );
+
+        drain_tx.send_replace(true);
+
+        tokio::select! {
+            _ = drain_tx.closed() => Ok(true),
+            _ = tokio::time::sleep(shutdown::remaining()) => Ok(false),
+        }
     }
 }
 
@@ -73,3 +98,105 @@ impl Default for ApiServer {
         Self::new()
     }
 }
+
+// Handshake is limited like request headers, so a client stalled in it can't hold the task
+async fn handshake(
+    acceptor: TlsAcceptor,
+    stream: TcpStream,
+    addr: SocketAddr,
+) -> Option<TlsStream<TcpStream>> {
+    let timeout = time::Duration::from_secs(config::CONFIG.server.limits.header_timeout);
+
+    match tokio::time::timeout(timeout, acceptor.accept(stream)).await {
+        Ok(Ok(tls_stream)) => Some(tls_stream),
+        Ok(Err(e)) => {
+            metrics::tls_handshake_failed();
+            warn!(ip:% = addr.ip(); "TLS error: {:?}", e);
+            None
+        }
+        Err(_) => {
+            metrics::tls_handshake_failed();
+            warn!(ip:% = addr.ip(); "TLS handshake timeout");
+            None
+        }
+    }
+}
+
+async fn serve<I>(stream: I, addr: SocketAddr, mut drain: watch::Receiver<bool>)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let io = TokioIo::new(stream);
+    let limits = &config::CONFIG.server.limits;
+
+    // HTTP/1.1 or HTTP/2 is chosen by ALPN for TLS or by connection preface for plain HTTP
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .header_read_timeout(time::Duration::from_secs(limits.header_timeout));
+
+    let h2_keep_alive_interval = match limits.h2_keep_alive_interval {
+        0 => None,
+        interval => Some(time::Duration::from_secs(interval)),
+    };
+
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(h2_keep_alive_interval);
+
+    let requests = sync::Arc::new(AtomicUsize::new(0));
+    let limit_reached = sync::Arc::new(Notify::new());
+    let keep_alive_requests = limits.keep_alive_requests;
+
+    let service = {
+        let limit_reached = limit_reached.clone();
+
+        service_fn(move |req: Request<Incoming>| {
+            let count = requests.fetch_add(1, Ordering::Relaxed) + 1;
+
+            if keep_alive_requests > 0 && count >= keep_alive_requests {
+                limit_reached.notify_one();
+            }
+
+            router::route(req, addr)
+        })
+    };
+
+    let conn = builder.serve_connection(io, service);
+    tokio::pin!(conn);
+
+    // Connection is closed gracefully on server shutdown or after its request limit:
+    // in-flight requests are finished, HTTP/1.1 client gets `Connection: close`
+    // and HTTP/2 client gets GOAWAY
+    let result = tokio::select! {
+        result = conn.as_mut() => result,
+        _ = drain.changed() => {
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+        _ = limit_reached.notified() => {
+            conn.as_mut().graceful_shutdown();
+            conn.await
+        }
+    };
+
+    if let Err(err) = result {
+        warn!(ip:% = addr.ip(); "Error serving connection: {:?}", err);
+    }
+}
+
+fn tls_config(ssl: &config::Ssl) -> Result<sync::Arc<ServerConfig>, GenericError> {
+    let certs = CertificateDer::pem_file_iter(ssl.cert.as_str())?.collect::<Result<_, _>>()?;
+    let private_key = PrivateKeyDer::from_pem_file(ssl.key.as_str())?;
+
+    let mut config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, private_key)?;
+
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(sync::Arc::new(config))
+}
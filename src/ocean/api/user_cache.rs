@@ -1,75 +1,357 @@
-use crate::db;
+use crate::config;
+use crate::metrics;
+use crate::session;
 use crate::types;
+use chrono::NaiveDateTime;
+use chrono::prelude::*;
 use diesel::prelude::*;
-use std::collections::HashMap;
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::BuildHasher;
 use std::sync::LazyLock;
 use std::sync::Mutex;
+use std::time;
 
-static USER_CACHE: LazyLock<Mutex<HashMap<String, types::User>>> =
-    LazyLock::new(|| Mutex::new(HashMap::new()));
+// Requests wait only for requests with tokens of the same shard
+const SHARDS: usize = 16;
 
-pub fn init(mut db: db::Db) {
+static HASHER: LazyLock<RandomState> = LazyLock::new(RandomState::new);
+
+static USER_CACHE: LazyLock<Vec<Mutex<Shard>>> = LazyLock::new(|| {
+    let config = &config::CONFIG.user_cache;
+
+    (0..SHARDS)
+        .map(|_| {
+            Mutex::new(Shard::new(
+                config.capacity.div_ceil(SHARDS),
+                config.unknown_capacity.div_ceil(SHARDS),
+            ))
+        })
+        .collect()
+});
+
+#[derive(Clone)]
+pub struct Session {
+    pub id: types::Id,
+    pub user: types::User,
+    pub expire_ts: Option<NaiveDateTime>,
+}
+
+pub enum Lookup {
+    Found(Session),
+    Unknown, // Token was recently not found in database
+    Miss,
+}
+
+struct Entry {
+    session: Session,
+    load_time: time::Instant,
+}
+
+struct Shard {
+    entries: HashMap<String, Entry>,                    // by token hash
+    user_sessions: HashMap<types::Id, HashSet<String>>, // token hashes by user id
+    order: VecDeque<(String, time::Instant)>,           // token hashes by load time
+    unknown: HashMap<String, time::Instant>,            // load time by token hash
+    unknown_order: VecDeque<(String, time::Instant)>,   // token hashes by load time
+    capacity: usize,                                    // sessions
+    unknown_capacity: usize,                            // unknown tokens
+    generation: u64,                                    // changed by every write
+}
+
+impl Shard {
+    fn new(capacity: usize, unknown_capacity: usize) -> Self {
+        Shard {
+            entries: HashMap::new(),
+            user_sessions: HashMap::new(),
+            order: VecDeque::new(),
+            unknown: HashMap::new(),
+            unknown_order: VecDeque::new(),
+            capacity,
+            unknown_capacity,
+            generation: 0,
+        }
+    }
+
+    fn insert(&mut self, token_hash: String, session: Session, now: time::Instant) {
+        self.remove(&token_hash);
+        self.unknown.remove(&token_hash);
+
+        self.user_sessions
+            .entry(session.user.id)
+            .or_default()
+            .insert(token_hash.clone());
+        self.order.push_back((token_hash.clone(), now));
+        self.entries.insert(
+            token_hash,
+            Entry {
+                session,
+                load_time: now,
+            },
+        );
+
+        // The earliest loaded sessions are evicted first
+        while self.entries.len() > self.capacity {
+            let Some((token_hash, load_time)) = self.order.pop_front() else {
+                break;
+            };
+
+            if self.is_current(&token_hash, load_time) {
+                self.remove(&token_hash);
+            }
+        }
+
+        // Removed and reloaded sessions leave outdated items in load order
+        if self.order.len() > 2 * self.capacity {
+            let entries = &self.entries;
+            self.order
+                .retain(|(h, t)| entries.get(h).is_some_and(|e| e.load_time == *t));
+        }
+    }
+
+    fn insert_unknown(&mut self, token_hash: String, now: time::Instant) {
+        self.unknown.insert(token_hash.clone(), now);
+        self.unknown_order.push_back((token_hash, now));
+
+        while self.unknown.len() > self.unknown_capacity {
+            let Some((token_hash, load_time)) = self.unknown_order.pop_front() else {
+                break;
+            };
+
+            if self.unknown.get(&token_hash) == Some(&load_time) {
+                self.unknown.remove(&token_hash);
+            }
+        }
+
+        if self.unknown_order.len() > 2 * self.unknown_capacity {
+            let unknown = &self.unknown;
+            self.unknown_order
+                .retain(|(h, t)| unknown.get(h) == Some(t));
+        }
+    }
+
+    // Result of load started at generation is kept only if the shard was not written since,
+    // so a session created or revoked meanwhile is not overwritten by stale data
+    fn insert_loaded(
+        &mut self,
+        generation: u64,
+        token_hash: String,
+        session: Option<Session>,
+        now: time::Instant,
+    ) {
+        if self.generation != generation {
+            return;
+        }
+
+        match session {
+            Some(s) => self.insert(token_hash, s, now),
+            None => self.insert_unknown(token_hash, now),
+        }
+    }
+
+    fn remove(&mut self, token_hash: &str) {
+        let Some(entry) = self.entries.remove(token_hash) else {
+            return;
+        };
+
+        let user_id = entry.session.user.id;
+
+        if let Some(hashes) = self.user_sessions.get_mut(&user_id) {
+            hashes.remove(token_hash);
+
+            if hashes.is_empty() {
+                self.user_sessions.remove(&user_id);
+            }
+        }
+    }
+
+    fn is_current(&self, token_hash: &str, load_time: time::Instant) -> bool {
+        self.entries
+            .get(token_hash)
+            .is_some_and(|e| e.load_time == load_time)
+    }
+
+    // Session is returned while it is fresh, unknown token while it is fresh as well
+    fn lookup(
+        &mut self,
+        token_hash: &str,
+        now: time::Instant,
+        utc_now: NaiveDateTime,
+        ttl: time::Duration,
+        unknown_ttl: time::Duration,
+    ) -> Lookup {
+        if let Some(entry) = self.entries.get(token_hash) {
+            let expired = entry.session.expire_ts.is_some_and(|ts| ts <= utc_now);
+
+            if !expired && now.duration_since(entry.load_time) < ttl {
+                return Lookup::Found(entry.session.clone());
+            }
+
+            self.remove(token_hash);
+        }
+
+        if let Some(load_time) = self.unknown.get(token_hash) {
+            if now.duration_since(*load_time) < unknown_ttl {
+                return Lookup::Unknown;
+            }
+
+            self.unknown.remove(token_hash);
+        }
+
+        Lookup::Miss
+    }
+}
+
+fn shard(token_hash: &str) -> &'static Mutex<Shard> {
+    &USER_CACHE[HASHER.hash_one(token_hash) as usize % SHARDS]
+}
+
+pub fn len() -> usize {
+    USER_CACHE
+        .iter()
+        .map(|s| s.lock().unwrap().entries.len())
+        .sum()
+}
+
+// Used for sessions created or changed by this instance
+pub fn set(token_hash: &str, session: Session) {
+    let mut shard = shard(token_hash).lock().unwrap();
+    shard.generation += 1;
+    shard.insert(token_hash.to_string(), session, time::Instant::now());
+}
+
+// Session is loaded from database again after ttl, so changes made by other instances
+// are applied within it. Unknown token is looked up again after its own shorter ttl.
+pub fn lookup(token: &str) -> Lookup {
+    let token_hash = session::hash_token(token);
+    let config = &config::CONFIG.user_cache;
+
+    let lookup = shard(&token_hash).lock().unwrap().lookup(
+        &token_hash,
+        time::Instant::now(),
+        Utc::now().naive_utc(),
+        time::Duration::from_secs(config.ttl),
+        time::Duration::from_secs(config.unknown_ttl),
+    );
+
+    match lookup {
+        Lookup::Found(_) => metrics::user_cache_hit(),
+        Lookup::Unknown | Lookup::Miss => metrics::user_cache_miss(),
+    }
+
+    lookup
+}
+
+pub fn get(token: &str) -> Option<Session> {
+    match lookup(token) {
+        Lookup::Found(session) => Some(session),
+        Lookup::Unknown | Lookup::Miss => None,
+    }
+}
+
+// Loads session that is absent in cache or stale
+pub fn load(conn: &mut PgConnection, token: &str) -> QueryResult<Option<Session>> {
+    use crate::model::schema::sessions;
     use crate::model::schema::user_groups;
-    use crate::model::schema::user_groups::dsl::*;
     use crate::model::schema::users;
-    use crate::model::schema::users::dsl::*;
 
     #[derive(Queryable)]
-    struct UserData {
+    struct SessionData {
         id: types::Id,
+        expire_ts: Option<NaiveDateTime>,
+        user_id: types::Id,
         name: String,
-        token: String,
         code: String,
         blocked: bool,
     }
 
-    let list = users
-        .inner_join(user_groups)
+    let token_hash = session::hash_token(token);
+    let now = Utc::now().naive_utc();
+
+    let generation = shard(&token_hash).lock().unwrap().generation;
+
+    let session_data = sessions::table
+        .inner_join(users::table.inner_join(user_groups::table))
+        .filter(sessions::token_hash.eq(&token_hash))
+        .filter(
+            sessions::expire_ts
+                .is_null()
+                .or(sessions::expire_ts.gt(now)),
+        )
         .select((
+            sessions::id,
+            sessions::expire_ts,
             users::id,
             users::name,
-            users::token,
             user_groups::code,
             users::blocked,
         ))
-        .load::<UserData>(&mut db.conn)
-        .unwrap();
-
-    for user_data in list {
-        let user = types::User {
-            id: user_data.id,
-            code: user_code(&user_data.code),
-            name: user_data.name,
-            blocked: user_data.blocked,
-        };
+        .first::<SessionData>(conn)
+        .optional()?;
 
-        USER_CACHE.lock().unwrap().insert(user_data.token, user);
-    }
+    let session = session_data.map(|data| Session {
+        id: data.id,
+        user: types::User {
+            id: data.user_id,
+            code: user_code(&data.code),
+            name: data.name,
+            blocked: data.blocked,
+        },
+        expire_ts: data.expire_ts,
+    });
+
+    shard(&token_hash).lock().unwrap().insert_loaded(
+        generation,
+        token_hash,
+        session.clone(),
+        time::Instant::now(),
+    );
+
+    Ok(session)
 }
 
-pub fn set(token: &str, user: types::User) {
-    USER_CACHE.lock().unwrap().insert(token.to_string(), user);
+// Tokens recently not found in database are looked up again
+pub fn find(conn: &mut PgConnection, token: &str) -> QueryResult<Option<Session>> {
+    match get(token) {
+        Some(session) => Ok(Some(session)),
+        None => load(conn, token),
+    }
 }
 
-pub fn get(token: &str) -> Option<types::User> {
-    USER_CACHE.lock().unwrap().get(token).map(|u| (*u).clone())
+pub fn remove(token_hash: &str) {
+    let mut shard = shard(token_hash).lock().unwrap();
+    shard.generation += 1;
+    shard.remove(token_hash);
 }
 
-pub fn update_blocked(id: types::Id, blocked: bool) {
-    let mut token: String = String::new();
-    let mut user: Option<types::User> = None;
+// Applies changed name, group or blocking to all cached sessions of user
+pub fn update_user(user: &types::User) {
+    for shard in USER_CACHE.iter() {
+        let mut shard = shard.lock().unwrap();
+        shard.generation += 1;
 
-    for (key, value) in USER_CACHE.lock().unwrap().iter() {
-        if value.id == id {
-            token = (*key).clone();
-            user = Some((*value).clone());
-            break;
+        let Shard {
+            entries,
+            user_sessions,
+            ..
+        } = &mut *shard;
+
+        for token_hash in user_sessions.get(&user.id).into_iter().flatten() {
+            if let Some(entry) = entries.get_mut(token_hash) {
+                entry.session.user = user.clone();
+            }
         }
     }
+}
 
-    if let Some(mut u) = user {
-        u.blocked = blocked;
-        set(&token, u);
+pub fn remove_user(id: types::Id) {
+    for shard in USER_CACHE.iter() {
+        let mut shard = shard.lock().unwrap();
+        shard.generation += 1;
+
+        for token_hash in shard.user_sessions.remove(&id).unwrap_or_default() {
+            shard.entries.remove(&token_hash);
+        }
     }
 }
 
@@ -81,3 +363,166 @@ pub fn user_code(code: &str) -> types::UserCode {
         _ => panic!("Unknown user code {}", code),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TTL: time::Duration = time::Duration::from_secs(60);
+    const UNKNOWN_TTL: time::Duration = time::Duration::from_secs(10);
+
+    fn session(id: types::Id, user_id: types::Id) -> Session {
+        Session {
+            id,
+            user: types::User {
+                id: user_id,
+                code: types::UserCode::User,
+                name: String::new(),
+                blocked: false,
+            },
+            expire_ts: None,
+        }
+    }
+
+    fn lookup(shard: &mut Shard, token_hash: &str, now: time::Instant) -> Lookup {
+        shard.lookup(token_hash, now, Utc::now().naive_utc(), TTL, UNKNOWN_TTL)
+    }
+
+    fn found(lookup: Lookup) -> Option<types::Id> {
+        match lookup {
+            Lookup::Found(s) => Some(s.id),
+            Lookup::Unknown | Lookup::Miss => None,
+        }
+    }
+
+    #[test]
+    fn earliest_loaded_is_evicted() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        shard.insert("a".to_string(), session(1, 1), now);
+        shard.insert("b".to_string(), session(2, 1), now);
+        shard.insert("c".to_string(), session(3, 2), now);
+
+        assert_eq!(found(lookup(&mut shard, "a", now)), None);
+        assert_eq!(found(lookup(&mut shard, "b", now)), Some(2));
+        assert_eq!(found(lookup(&mut shard, "c", now)), Some(3));
+        assert_eq!(shard.user_sessions[&1], HashSet::from(["b".to_string()]));
+    }
+
+    #[test]
+    fn reloaded_is_not_evicted() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        shard.insert("a".to_string(), session(1, 1), now);
+        shard.insert("b".to_string(), session(2, 1), now);
+        shard.insert("a".to_string(), session(1, 1), now + TTL);
+        shard.insert("c".to_string(), session(3, 1), now + TTL);
+
+        assert_eq!(found(lookup(&mut shard, "a", now + TTL)), Some(1));
+        assert_eq!(found(lookup(&mut shard, "b", now + TTL)), None);
+    }
+
+    #[test]
+    fn stale_session_is_removed() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        shard.insert("a".to_string(), session(1, 1), now);
+
+        assert_eq!(found(lookup(&mut shard, "a", now + TTL / 2)), Some(1));
+        assert!(matches!(lookup(&mut shard, "a", now + TTL), Lookup::Miss));
+        assert!(shard.entries.is_empty());
+        assert!(shard.user_sessions.is_empty());
+    }
+
+    #[test]
+    fn expired_session_is_removed() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        let expired = Session {
+            expire_ts: Some(Utc::now().naive_utc()),
+            ..session(1, 1)
+        };
+
+        shard.insert("a".to_string(), expired, now);
+
+        assert!(matches!(lookup(&mut shard, "a", now), Lookup::Miss));
+    }
+
+    #[test]
+    fn unknown_token_expires() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        shard.insert_unknown("a".to_string(), now);
+
+        assert!(matches!(lookup(&mut shard, "a", now), Lookup::Unknown));
+        assert!(matches!(
+            lookup(&mut shard, "a", now + UNKNOWN_TTL),
+            Lookup::Miss
+        ));
+    }
+
+    #[test]
+    fn unknown_token_is_replaced_by_session() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        shard.insert_unknown("a".to_string(), now);
+        shard.insert("a".to_string(), session(1, 1), now);
+
+        assert_eq!(found(lookup(&mut shard, "a", now)), Some(1));
+    }
+
+    #[test]
+    fn unknown_tokens_are_bounded() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        for token_hash in ["a", "b", "c"] {
+            shard.insert_unknown(token_hash.to_string(), now);
+        }
+
+        assert_eq!(shard.unknown.len(), 2);
+        assert!(matches!(lookup(&mut shard, "a", now), Lookup::Miss));
+        assert!(matches!(lookup(&mut shard, "c", now), Lookup::Unknown));
+    }
+
+    #[test]
+    fn stale_load_is_discarded() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+        let generation = shard.generation;
+
+        // Session is revoked while it is loaded
+        shard.generation += 1;
+        shard.insert_loaded(generation, "a".to_string(), Some(session(1, 1)), now);
+        shard.insert_loaded(generation, "b".to_string(), None, now);
+
+        assert!(matches!(lookup(&mut shard, "a", now), Lookup::Miss));
+        assert!(matches!(lookup(&mut shard, "b", now), Lookup::Miss));
+
+        shard.insert_loaded(shard.generation, "a".to_string(), Some(session(1, 1)), now);
+        shard.insert_loaded(shard.generation, "b".to_string(), None, now);
+
+        assert_eq!(found(lookup(&mut shard, "a", now)), Some(1));
+        assert!(matches!(lookup(&mut shard, "b", now), Lookup::Unknown));
+    }
+
+    #[test]
+    fn order_is_compacted() {
+        let mut shard = Shard::new(2, 2);
+        let now = time::Instant::now();
+
+        for i in 0..10 {
+            shard.insert("a".to_string(), session(1, 1), now + TTL * i);
+        }
+
+        assert!(shard.order.len() <= 2 * shard.capacity);
+        assert!(shard.order.iter().any(|(h, t)| shard.is_current(h, *t)));
+        assert_eq!(shard.entries.len(), 1);
+    }
+}
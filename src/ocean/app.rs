@@ -1,7 +1,16 @@
+use crate::api::rate_limiter;
 use crate::api::server;
 use crate::config;
+use crate::session;
+use crate::shutdown;
 use crate::trash_monitor;
 use crate::watchdog;
+use log::{info, warn};
+use std::process::ExitCode;
+
+// Process exit codes
+pub const EXIT_WATCHDOG: u8 = 2;
+pub const EXIT_DRAIN_TIMEOUT: u8 = 3;
 
 pub struct App;
 
@@ -10,16 +19,51 @@ impl App {
         Self
     }
 
-    pub async fn start(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-        if config::CONFIG.watchdog.enabled {
-            watchdog::start();
-        }
+    pub async fn start(&self) -> Result<ExitCode, Box<dyn std::error::Error + Send + Sync>> {
+        let watchdog = if config::CONFIG.watchdog.enabled {
+            Some(watchdog::start())
+        } else {
+            None
+        };
 
-        trash_monitor::start();
+        let trash_monitor = trash_monitor::start();
+        let session_monitor = session::start();
+        let rate_limiter = rate_limiter::start();
 
         let server = server::ApiServer::new();
-        server.listen().await?;
-        Ok(())
+        let result = server.listen(shutdown::wait()).await;
+
+        // Workers finish their running tasks, waiting for them must not block async runtime
+        let workers = tokio::task::spawn_blocking(move || {
+            rate_limiter.stop();
+            session_monitor.stop();
+            trash_monitor.stop();
+
+            if let Some(w) = watchdog {
+                w.stop();
+            }
+        });
+
+        if tokio::time::timeout(shutdown::remaining(), workers)
+            .await
+            .is_err()
+        {
+            warn!("Workers are not stopped in time");
+        }
+
+        let drained = result?;
+
+        let code = if shutdown::reason() == Some(shutdown::Reason::Watchdog) {
+            EXIT_WATCHDOG
+        } else if !drained {
+            warn!("Connections are not drained in time");
+            EXIT_DRAIN_TIMEOUT
+        } else {
+            0
+        };
+
+        info!("Ocean stopped");
+        Ok(ExitCode::from(code))
     }
 }
 
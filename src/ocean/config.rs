@@ -1,46 +1,236 @@
+use crate::api::proxy::Cidr;
+use crate::types::UserCode;
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema};
 use serde_derive::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::sync::LazyLock;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{LazyLock, OnceLock};
+use std::time;
 
-pub static CONFIG: LazyLock<Config> = LazyLock::new(|| Config::new());
+static INSTANCE: OnceLock<Config> = OnceLock::new();
 
-#[derive(Debug, Deserialize)]
+pub static CONFIG: LazyLock<&'static Config> = LazyLock::new(|| INSTANCE.get_or_init(Config::new));
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Config {
     pub server: Server,
     pub frontend: Frontend,
     pub postgres: Postgres,
     pub telegram_bot: TelegramBot,
     pub watchdog: Watchdog,
+    #[serde(default)]
+    pub log: Log,
+    #[serde(default)]
+    pub ownership: Ownership,
+    #[serde(default)]
+    pub sessions: Sessions,
+    #[serde(default)]
+    pub user_cache: UserCache,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Server {
     pub port: u16,
     pub anonym_allowed: bool,
-    pub ssl: Ssl,
+    pub ssl: Option<Ssl>, // Plain HTTP is used when absent, e.g. behind reverse proxy
+    #[serde(default)]
+    #[schemars(with = "Vec<String>")]
+    pub trusted_proxies: Vec<Cidr>,
+    #[serde(default)]
+    pub timeout: Timeout,
+    #[serde(default = "default_drain_timeout")]
+    pub drain_timeout: u64, // seconds to finish in-flight requests on shutdown
+    #[serde(default)]
+    pub rate_limit: HashMap<String, RateLimit>, // by method name
+    #[serde(default)]
+    pub cors: Cors,
+    #[serde(default)]
+    pub limits: Limits,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default = "default_metrics_allowed")]
+    #[schemars(with = "Vec<String>")]
+    pub metrics_allowed: Vec<Cidr>, // client addresses allowed to read /metrics
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Compression {
+    pub enabled: bool,
+    pub min_size: usize, // bytes
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            enabled: true,
+            min_size: 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Limits {
+    pub max_body_size: usize,        // bytes
+    pub header_timeout: u64,         // seconds, also closes idle keep-alive connections
+    pub body_timeout: u64,           // seconds
+    pub keep_alive_requests: usize,  // requests per connection, 0 is unlimited
+    pub h2_keep_alive_interval: u64, // seconds between HTTP/2 pings, 0 disables them
+    pub max_batch: usize,            // calls in one batch request
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_body_size: 1024 * 1024,
+            header_timeout: 30,
+            body_timeout: 30,
+            keep_alive_requests: 1000,
+            h2_keep_alive_interval: 60,
+            max_batch: 100,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Cors {
+    pub allowed_origins: Vec<String>, // frontend.domen is used when empty
+    pub allowed_headers: Vec<String>,
+    pub max_age: u64, // seconds
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Cors {
+            allowed_origins: Vec::new(),
+            allowed_headers: vec!["Authorization".to_string(), "Content-Type".to_string()],
+            max_age: 24 * 60 * 60,
+        }
+    }
+}
+
+// Number of calls allowed per period for each user group, absent group is not limited
+#[derive(Debug, Deserialize, JsonSchema)]
+pub struct RateLimit {
+    pub period: u64, // seconds
+    pub anonym: Option<u32>,
+    pub user: Option<u32>,
+    pub admin: Option<u32>,
+}
+
+impl RateLimit {
+    pub fn budget(&self, code: &UserCode) -> Option<u32> {
+        match code {
+            UserCode::Anonym => self.anonym,
+            UserCode::User => self.user,
+            UserCode::Admin => self.admin,
+        }
+    }
+}
+
+fn default_drain_timeout() -> u64 {
+    30
 }
 
-#[derive(Debug, Deserialize)]
+fn default_metrics_allowed() -> Vec<Cidr> {
+    vec![
+        Cidr::from_str("127.0.0.1").unwrap(),
+        Cidr::from_str("::1").unwrap(),
+    ]
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Timeout {
+    pub default: u64,                  // seconds
+    pub methods: HashMap<String, u64>, // seconds per method name
+}
+
+impl Timeout {
+    pub fn method(&self, name: &str) -> time::Duration {
+        time::Duration::from_secs(*self.methods.get(name).unwrap_or(&self.default))
+    }
+}
+
+impl Default for Timeout {
+    fn default() -> Self {
+        Timeout {
+            default: 30,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Ssl {
     pub cert: String,
     pub key: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Frontend {
     pub domen: String,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
+#[serde(default)]
 pub struct Postgres {
+    pub url: Option<String>, // Overrides all connection fields below
     pub host: String,
     pub port: u16,
+    pub socket: Option<String>, // Unix socket directory, used instead of host
     pub username: String,
     pub password: String,
     pub database: String,
+    pub sslmode: Option<String>,
+    pub pool: Pool,
 }
 
-#[derive(Debug, Deserialize)]
+impl Default for Postgres {
+    fn default() -> Self {
+        Postgres {
+            url: None,
+            host: "localhost".to_string(),
+            port: 5432,
+            socket: None,
+            username: "ocean".to_string(),
+            password: "".to_string(),
+            database: "ocean".to_string(),
+            sslmode: None,
+            pool: Pool::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Clone)]
+#[serde(default)]
+pub struct Pool {
+    pub min_size: u32,
+    pub max_size: u32,
+    pub timeout: u64, // seconds
+    pub health_check: bool,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Pool {
+            min_size: 1,
+            max_size: 10,
+            timeout: 30,
+            health_check: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct TelegramBot {
     pub token: String,
     pub url: String,
@@ -49,23 +239,323 @@ pub struct TelegramBot {
     pub enabled: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, JsonSchema)]
 pub struct Watchdog {
     pub enabled: bool,
     pub anonym_token: String,
 }
 
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Log {
+    pub format: LogFormat,
+    pub body: BodyLog,
+    pub max_body_size: usize,              // characters of truncated body
+    pub methods: HashMap<String, BodyLog>, // body policy per method name
+}
+
+impl Log {
+    // Batch is logged with the strictest policy of its methods
+    pub fn body_policy<'a>(&self, methods: impl Iterator<Item = &'a str>) -> BodyLog {
+        methods
+            .map(|m| *self.methods.get(m).unwrap_or(&self.body))
+            .max()
+            .unwrap_or(self.body)
+    }
+}
+
+impl Default for Log {
+    fn default() -> Self {
+        Log {
+            format: LogFormat::Text,
+            body: BodyLog::Full,
+            max_body_size: 2048,
+            methods: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    Json, // One JSON object per line
+}
+
+// Ordered from the least to the most strict
+#[derive(Debug, Deserialize, JsonSchema, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyLog {
+    Full,
+    Truncate,
+    Redact, // Only size of body is logged
+}
+
+#[derive(Debug, Deserialize, JsonSchema, Default)]
+#[serde(default)]
+pub struct Ownership {
+    pub edit_window: Option<u64>, // seconds for author to change content, unlimited when absent
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct Sessions {
+    pub token_secret: String, // key of token hashes, changing it invalidates all tokens
+    pub ttl: u64,             // seconds of session created by user.auth
+    pub touch_interval: u64,  // seconds between writes of last seen time to database
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Sessions {
+            token_secret: String::new(),
+            ttl: 30 * 24 * 60 * 60,
+            touch_interval: 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct UserCache {
+    pub capacity: usize,         // sessions
+    pub ttl: u64,                // seconds before session is loaded from database again
+    pub unknown_capacity: usize, // tokens not found in database
+    pub unknown_ttl: u64,        // seconds before unknown token is looked up again
+    pub ip_unknown_tokens: u32,  // tokens not found in database per minute by IP
+}
+
+impl Default for UserCache {
+    fn default() -> Self {
+        UserCache {
+            capacity: 100_000,
+            ttl: 60,
+            unknown_capacity: 10_000,
+            unknown_ttl: 10,
+            ip_unknown_tokens: 60,
+        }
+    }
+}
+
+pub const CONFIG_ENV: &str = "OCEAN_CONFIG";
+pub const CONFIG_FLAG: &str = "--config";
+const ENV_PREFIX: &str = "OCEAN_";
+const ENV_SEPARATOR: &str = "__";
+const SAMPLE_TOKEN_SECRET: &str = "replace-with-random-secret"; // in configs/ocean.toml
+
+#[derive(Debug)]
+pub enum Error {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, String),
+    Invalid(Vec<String>),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Read(path, e) => write!(f, "Failed to read config {}: {}", path.display(), e),
+            Error::Parse(path, e) => write!(f, "Failed to parse config {}: {}", path.display(), e),
+            Error::Invalid(problems) => {
+                write!(f, "Invalid config:")?;
+                for problem in problems {
+                    write!(f, "\n  {}", problem)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+// Loads config once. Must be called before first access to `CONFIG`,
+// otherwise config is loaded lazily and any error leads to panic.
+pub fn init() -> Result<(), Error> {
+    let config = Config::load()?;
+    INSTANCE.set(config).ok();
+    Ok(())
+}
+
 impl Config {
     pub fn new() -> Self {
-        let mut config_path = dirs::config_dir().unwrap();
-        config_path.push("ocean/ocean.toml");
+        Self::load().unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    pub fn load() -> Result<Self, Error> {
+        let config_path = path();
+
+        let config_text =
+            fs::read_to_string(&config_path).map_err(|e| Error::Read(config_path.clone(), e))?;
+
+        let mut table = toml::from_str::<toml::Table>(&config_text)
+            .map_err(|e| Error::Parse(config_path.clone(), e.to_string()))?;
+
+        // Problems of environment, types and values are reported together
+        let schema = config_schema();
+        let mut problems = apply_env(schema.as_value(), &mut table, env::vars());
+        let type_problems = check_value(schema.as_value(), &toml::Value::Table(table.clone()), "");
+
+        if type_problems.is_empty() {
+            // Round trip through text gives errors with key path
+            let config_text = toml::to_string(&table).unwrap();
 
-        if !config_path.exists() {
-            panic!("config path not exists: {}", config_path.to_str().unwrap());
+            match toml::from_str::<Config>(&config_text) {
+                Ok(config) => {
+                    problems.extend(config.validate());
+
+                    if problems.is_empty() {
+                        return Ok(config);
+                    }
+                }
+                Err(e) if problems.is_empty() => {
+                    return Err(Error::Parse(config_path, e.to_string()));
+                }
+                Err(e) => problems.push(e.to_string()),
+            }
+        } else {
+            problems.extend(type_problems);
         }
 
-        let config_text = fs::read_to_string(config_path).unwrap();
-        toml::from_str(&config_text).unwrap()
+        Err(Error::Invalid(problems))
+    }
+
+    // Collects all problems at once with the field path of each
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.server.port == 0 {
+            problems.push("server.port: must not be 0".to_string());
+        }
+
+        if let Some(ssl) = &self.server.ssl {
+            for (field, file) in [("server.ssl.cert", &ssl.cert), ("server.ssl.key", &ssl.key)] {
+                if !Path::new(file).is_file() {
+                    problems.push(format!("{}: file not exists: {}", field, file));
+                }
+            }
+        }
+
+        let limits = &self.server.limits;
+
+        for (field, value) in [
+            ("server.limits.max_body_size", limits.max_body_size as u64),
+            ("server.limits.header_timeout", limits.header_timeout),
+            ("server.limits.body_timeout", limits.body_timeout),
+            ("server.limits.max_batch", limits.max_batch as u64),
+        ] {
+            if value == 0 {
+                problems.push(format!("{}: must not be 0", field));
+            }
+        }
+
+        if self.server.timeout.default == 0 {
+            problems.push("server.timeout.default: must not be 0".to_string());
+        }
+
+        for (method, limit) in &self.server.rate_limit {
+            if limit.period == 0 {
+                problems.push(format!(
+                    "server.rate_limit.\"{}\".period: must not be 0",
+                    method
+                ));
+            }
+
+            for (group, budget) in [
+                ("anonym", limit.anonym),
+                ("user", limit.user),
+                ("admin", limit.admin),
+            ] {
+                if budget == Some(0) {
+                    problems.push(format!(
+                        "server.rate_limit.\"{}\".{}: must not be 0",
+                        method, group
+                    ));
+                }
+            }
+        }
+
+        if self.log.max_body_size == 0 {
+            problems.push("log.max_body_size: must not be 0".to_string());
+        }
+
+        if self.ownership.edit_window == Some(0) {
+            problems.push("ownership.edit_window: must not be 0".to_string());
+        }
+
+        if self.sessions.token_secret.is_empty() {
+            problems.push("sessions.token_secret: must not be empty".to_string());
+        } else if self.sessions.token_secret == SAMPLE_TOKEN_SECRET {
+            problems
+                .push("sessions.token_secret: must be replaced with a random secret".to_string());
+        }
+
+        for (field, value) in [
+            ("sessions.ttl", self.sessions.ttl),
+            ("sessions.touch_interval", self.sessions.touch_interval),
+        ] {
+            if value == 0 {
+                problems.push(format!("{}: must not be 0", field));
+            }
+        }
+
+        for (field, value) in [
+            ("user_cache.capacity", self.user_cache.capacity as u64),
+            ("user_cache.ttl", self.user_cache.ttl),
+            (
+                "user_cache.unknown_capacity",
+                self.user_cache.unknown_capacity as u64,
+            ),
+            ("user_cache.unknown_ttl", self.user_cache.unknown_ttl),
+            (
+                "user_cache.ip_unknown_tokens",
+                self.user_cache.ip_unknown_tokens as u64,
+            ),
+        ] {
+            if value == 0 {
+                problems.push(format!("{}: must not be 0", field));
+            }
+        }
+
+        if self.frontend.domen.is_empty() {
+            problems.push("frontend.domen: must not be empty".to_string());
+        }
+
+        let postgres = &self.postgres;
+
+        if postgres.url.is_none() && postgres.database.is_empty() {
+            problems.push("postgres.database: must not be empty".to_string());
+        }
+
+        if postgres.pool.max_size == 0 {
+            problems.push("postgres.pool.max_size: must not be 0".to_string());
+        }
+
+        if postgres.pool.min_size > postgres.pool.max_size {
+            problems.push("postgres.pool.min_size: must not exceed max_size".to_string());
+        }
+
+        let bot = &self.telegram_bot;
+
+        if bot.enabled {
+            for (field, value) in [
+                ("telegram_bot.token", &bot.token),
+                ("telegram_bot.url", &bot.url),
+                ("telegram_bot.channel", &bot.channel),
+                ("telegram_bot.admin_chat_id", &bot.admin_chat_id),
+            ] {
+                if value.is_empty() {
+                    problems.push(format!("{}: must not be empty when bot is enabled", field));
+                }
+            }
+        }
+
+        if self.watchdog.enabled && self.watchdog.anonym_token.is_empty() {
+            problems.push(
+                "watchdog.anonym_token: must not be empty when watchdog is enabled".to_string(),
+            );
+        }
+
+        problems
     }
 }
 
@@ -74,3 +564,460 @@ impl Default for Config {
         Self::new()
     }
 }
+
+// Priority: command line flag, environment variable, user config directory
+fn path() -> PathBuf {
+    if let Some(p) = path_from_args(env::args()) {
+        return p;
+    }
+
+    if let Ok(p) = env::var(CONFIG_ENV) {
+        return PathBuf::from(p);
+    }
+
+    let mut config_path = dirs::config_dir().unwrap_or_default();
+    config_path.push("ocean/ocean.toml");
+    config_path
+}
+
+fn path_from_args(args: impl Iterator<Item = String>) -> Option<PathBuf> {
+    let mut args = args.skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == CONFIG_FLAG {
+            return args.next().map(PathBuf::from);
+        }
+
+        if let Some(p) = arg
+            .strip_prefix(CONFIG_FLAG)
+            .and_then(|a| a.strip_prefix('='))
+        {
+            return Some(PathBuf::from(p));
+        }
+    }
+
+    None
+}
+
+// Overrides config fields with `OCEAN_SECTION__FIELD` environment variables,
+// e.g. `OCEAN_POSTGRES__POOL__MAX_SIZE=20`. Value type follows the type of
+// field in Config, lists are separated by commas. Field names are case-insensitive,
+// map keys are case-sensitive and method names in them are written with `_` for `.`,
+// e.g. `OCEAN_SERVER__TIMEOUT__METHODS__feed_getAll=5`.
+fn apply_env(
+    schema: &Value,
+    table: &mut toml::Table,
+    vars: impl Iterator<Item = (String, String)>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    for (key, raw) in vars {
+        if key == CONFIG_ENV {
+            continue;
+        }
+
+        let Some(name) = key.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let segments: Vec<&str> = name.split(ENV_SEPARATOR).collect();
+
+        let Some((keys, field_schema)) = resolve(schema, &segments) else {
+            let field = segments.join(".").to_lowercase();
+            problems.push(format!("{}: unknown config field {}", key, field));
+            continue;
+        };
+
+        let field = keys
+            .iter()
+            .fold(String::new(), |path, k| field_path(&path, k));
+        let (last, sections) = keys.split_last().unwrap();
+
+        let current = match section_mut(table, sections) {
+            Ok(t) => t,
+            Err(section) => {
+                problems.push(format!("{}: {} is not a section", key, section));
+                continue;
+            }
+        };
+
+        match env_value(field_schema, &raw) {
+            Some(value) => {
+                current.insert(last.clone(), value);
+            }
+            None => problems.push(format!("{}: invalid value for {}: {}", key, field, raw)),
+        }
+    }
+
+    problems
+}
+
+fn config_schema() -> Schema {
+    SchemaSettings::draft2020_12()
+        .with(|s| s.inline_subschemas = true)
+        .for_deserialize()
+        .into_generator()
+        .into_root_schema_for::<Config>()
+}
+
+// Keys and schema of nested field named by environment variable, None when there is no such
+// field. Method names have dots but no underscores, so `_` in map keys stands for `.`.
+fn resolve<'a>(schema: &'a Value, segments: &[&str]) -> Option<(Vec<String>, &'a Value)> {
+    let Some((first, rest)) = segments.split_first() else {
+        return Some((Vec::new(), schema));
+    };
+
+    if first.is_empty() {
+        return None;
+    }
+
+    let schema = not_null(schema);
+    let field = first.to_lowercase();
+
+    let (key, next) = match schema.get("properties").and_then(|p| p.get(&field)) {
+        Some(s) => (field, s),
+        None => (
+            first.replace('_', "."),
+            schema
+                .get("additionalProperties")
+                .filter(|s| s.is_object())?, // maps
+        ),
+    };
+
+    let (mut keys, field_schema) = resolve(next, rest)?;
+    keys.insert(0, key);
+    Some((keys, field_schema))
+}
+
+// Map keys with dots are quoted like in TOML
+fn field_path(path: &str, key: &str) -> String {
+    let key = if key.contains('.') {
+        format!("\"{}\"", key)
+    } else {
+        key.to_string()
+    };
+
+    if path.is_empty() {
+        key
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+// Checks types and allowed values by Config schema. Deserialization stops at the first
+// mistake, this reports all of them.
+fn check_value(schema: &Value, value: &toml::Value, path: &str) -> Vec<String> {
+    let schema = not_null(schema);
+
+    let Some(expected) = schema_type(schema) else {
+        return Vec::new();
+    };
+
+    let matches = matches!(
+        (expected, value),
+        ("string", toml::Value::String(_))
+            | ("integer", toml::Value::Integer(_))
+            | ("number", toml::Value::Integer(_) | toml::Value::Float(_))
+            | ("boolean", toml::Value::Boolean(_))
+            | ("array", toml::Value::Array(_))
+            | ("object", toml::Value::Table(_))
+    );
+
+    if !matches {
+        let expected = if expected == "object" {
+            "section"
+        } else {
+            expected
+        };
+        return vec![format!("{}: must be {}", path, expected)];
+    }
+
+    let mut problems = Vec::new();
+
+    match value {
+        toml::Value::String(s) => {
+            if let Some(variants) = schema.get("enum").and_then(Value::as_array)
+                && !variants.contains(&Value::from(s.as_str()))
+            {
+                let variants: Vec<&str> = variants.iter().filter_map(Value::as_str).collect();
+                problems.push(format!("{}: must be one of {}", path, variants.join(", ")));
+            }
+        }
+        toml::Value::Integer(i) => {
+            if let Some(min) = schema.get("minimum").and_then(Value::as_i64)
+                && *i < min
+            {
+                problems.push(format!("{}: must not be less than {}", path, min));
+            }
+
+            if let Some(max) = schema.get("maximum").and_then(Value::as_i64)
+                && *i > max
+            {
+                problems.push(format!("{}: must not exceed {}", path, max));
+            }
+        }
+        toml::Value::Array(items) => {
+            if let Some(items_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    let item_path = format!("{}[{}]", path, i);
+                    problems.extend(check_value(items_schema, item, &item_path));
+                }
+            }
+        }
+        toml::Value::Table(table) => {
+            let required = schema.get("required").and_then(Value::as_array);
+
+            for field in required.into_iter().flatten().filter_map(Value::as_str) {
+                if !table.contains_key(field) {
+                    problems.push(format!("{}: missing field", field_path(path, field)));
+                }
+            }
+
+            let properties = schema.get("properties");
+            let map_values = schema.get("additionalProperties").filter(|s| s.is_object());
+
+            for (key, value) in table {
+                let field_schema = properties.and_then(|p| p.get(key)).or(map_values);
+
+                if let Some(field_schema) = field_schema {
+                    problems.extend(check_value(field_schema, value, &field_path(path, key)));
+                }
+            }
+        }
+        _ => {}
+    }
+
+    problems
+}
+
+// Optional fields are described as a choice with null
+fn not_null(schema: &Value) -> &Value {
+    schema
+        .get("anyOf")
+        .and_then(Value::as_array)
+        .and_then(|a| {
+            a.iter()
+                .find(|s| s.get("type") != Some(&Value::from("null")))
+        })
+        .unwrap_or(schema)
+}
+
+fn schema_type(schema: &Value) -> Option<&str> {
+    match not_null(schema).get("type")? {
+        Value::String(t) => Some(t),
+        Value::Array(types) => types
+            .iter()
+            .filter_map(Value::as_str)
+            .find(|t| *t != "null"),
+        _ => None,
+    }
+}
+
+fn section_mut<'a>(
+    table: &'a mut toml::Table,
+    sections: &[String],
+) -> Result<&'a mut toml::Table, String> {
+    let Some((first, rest)) = sections.split_first() else {
+        return Ok(table);
+    };
+
+    let entry = table
+        .entry(first.clone())
+        .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+
+    match entry {
+        toml::Value::Table(t) => section_mut(t, rest),
+        _ => Err(first.clone()),
+    }
+}
+
+fn env_value(schema: &Value, raw: &str) -> Option<toml::Value> {
+    match schema_type(schema)? {
+        "string" => Some(toml::Value::String(raw.to_string())),
+        "integer" => raw.parse().ok().map(toml::Value::Integer),
+        "number" => raw.parse().ok().map(toml::Value::Float),
+        "boolean" => raw.parse().ok().map(toml::Value::Boolean),
+        "array" => {
+            let items = not_null(schema).get("items")?;
+
+            raw.split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(|s| env_value(items, s))
+                .collect::<Option<Vec<_>>>()
+                .map(toml::Value::Array)
+        }
+        _ => None, // sections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(list: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        list.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    fn schema(keys: &[&str]) -> Value {
+        let schema = config_schema();
+        resolve(schema.as_value(), keys).unwrap().1.clone()
+    }
+
+    fn apply(table: &mut toml::Table, list: &[(&str, &str)]) -> Vec<String> {
+        apply_env(config_schema().as_value(), table, vars(list))
+    }
+
+    #[test]
+    fn value_follows_field_type() {
+        let password = schema(&["postgres", "password"]);
+        assert_eq!(
+            env_value(&password, "12345"),
+            Some(toml::Value::String("12345".to_string()))
+        );
+
+        let port = schema(&["server", "port"]);
+        assert_eq!(env_value(&port, "8080"), Some(toml::Value::Integer(8080)));
+        assert_eq!(env_value(&port, "http"), None);
+
+        let enabled = schema(&["watchdog", "enabled"]);
+        assert_eq!(
+            env_value(&enabled, "true"),
+            Some(toml::Value::Boolean(true))
+        );
+        assert_eq!(env_value(&enabled, "1"), None);
+
+        let url = schema(&["postgres", "url"]);
+        assert_eq!(
+            env_value(&url, "postgres://localhost"),
+            Some(toml::Value::String("postgres://localhost".to_string()))
+        );
+
+        let edit_window = schema(&["ownership", "edit_window"]);
+        assert_eq!(
+            env_value(&edit_window, "60"),
+            Some(toml::Value::Integer(60))
+        );
+
+        let proxies = schema(&["server", "trusted_proxies"]);
+        assert_eq!(
+            env_value(&proxies, "10.0.0.0/8, 127.0.0.1"),
+            Some(toml::Value::Array(vec![
+                toml::Value::String("10.0.0.0/8".to_string()),
+                toml::Value::String("127.0.0.1".to_string()),
+            ]))
+        );
+
+        let pool = schema(&["postgres", "pool"]);
+        assert_eq!(env_value(&pool, "10"), None);
+    }
+
+    #[test]
+    fn env_overrides_table() {
+        let mut table: toml::Table = toml::from_str("[server]\nport = 80\n").unwrap();
+
+        let problems = apply(
+            &mut table,
+            &[
+                ("OCEAN_SERVER__PORT", "8080"),
+                ("OCEAN_postgres__Password", "12345"),
+                ("OCEAN_SERVER__TIMEOUT__METHODS__feed_getAll", "5"),
+                ("OCEAN_SERVER__RATE_LIMIT__user_auth__PERIOD", "60"),
+                ("OCEAN_LOG__METHODS__user_updateToken", "redact"),
+                ("OCEAN_CONFIG", "/etc/ocean.toml"),
+                ("PATH", "/bin"),
+            ],
+        );
+
+        assert!(problems.is_empty(), "{:?}", problems);
+        assert_eq!(table["server"]["port"], toml::Value::Integer(8080));
+        assert_eq!(
+            table["postgres"]["password"],
+            toml::Value::String("12345".to_string())
+        );
+        assert_eq!(
+            table["server"]["timeout"]["methods"]["feed.getAll"],
+            toml::Value::Integer(5)
+        );
+        assert_eq!(
+            table["server"]["rate_limit"]["user.auth"]["period"],
+            toml::Value::Integer(60)
+        );
+        assert_eq!(
+            table["log"]["methods"]["user.updateToken"],
+            toml::Value::String("redact".to_string())
+        );
+    }
+
+    #[test]
+    fn env_problems() {
+        let mut table: toml::Table = toml::from_str("frontend = 1\n").unwrap();
+
+        let problems = apply(
+            &mut table,
+            &[
+                ("OCEAN_UNKNOWN", "1"),
+                ("OCEAN_SERVER__UNKNOWN", "1"),
+                ("OCEAN_SERVER____PORT", "1"),
+                ("OCEAN_SERVER__PORT", "http"),
+                ("OCEAN_SERVER__TIMEOUT__METHODS__feed_getAll", "soon"),
+                ("OCEAN_FRONTEND__DOMEN", "example.com"),
+            ],
+        );
+
+        assert_eq!(
+            problems,
+            vec![
+                "OCEAN_UNKNOWN: unknown config field unknown",
+                "OCEAN_SERVER__UNKNOWN: unknown config field server.unknown",
+                "OCEAN_SERVER____PORT: unknown config field server..port",
+                "OCEAN_SERVER__PORT: invalid value for server.port: http",
+                "OCEAN_SERVER__TIMEOUT__METHODS__feed_getAll: \
+                 invalid value for server.timeout.methods.\"feed.getAll\": soon",
+                "OCEAN_FRONTEND__DOMEN: frontend is not a section",
+            ]
+        );
+    }
+
+    #[test]
+    fn all_type_problems_are_reported() {
+        let table: toml::Table = toml::from_str(
+            r#"
+            frontend = 1
+
+            [server]
+            port = 70000
+            trusted_proxies = ["127.0.0.1", 2]
+
+            [server.rate_limit."user.auth"]
+            user = -1
+
+            [log]
+            format = "xml"
+            "#,
+        )
+        .unwrap();
+
+        let problems = check_value(config_schema().as_value(), &toml::Value::Table(table), "");
+
+        for problem in [
+            "frontend: must be section",
+            "postgres: missing field",
+            "server.port: must not exceed 65535",
+            "server.trusted_proxies[1]: must be string",
+            "server.rate_limit.\"user.auth\".period: missing field",
+            "server.rate_limit.\"user.auth\".user: must not be less than 0",
+            "log.format: must be one of text, json",
+        ] {
+            assert!(
+                problems.iter().any(|p| p == problem),
+                "{}: {:?}",
+                problem,
+                problems
+            );
+        }
+    }
+}
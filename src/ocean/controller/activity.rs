@@ -1,27 +1,25 @@
 use crate::controller::forum;
 use crate::controller::mandela;
 use crate::controller::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // activity.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        limit: i32,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    limit: i32,
+}
 
-    #[derive(Serialize)]
-    struct Resp {
-        comments: Vec<mandela::Comment>,
-        topics: Vec<forum::Topic>,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    comments: Vec<mandela::Comment>,
+    topics: Vec<forum::Topic>,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     let topics = forum::new_topics(&mut data.db, req.limit, 0)?;
     let comments = mandela::new_comments(&mut data.db, req.limit, 0)?;
 
-    let resp = Resp { comments, topics };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    let resp = GetAllResp { comments, topics };
+    Ok(resp)
 }
@@ -6,18 +6,17 @@ use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int2, Int4, Int8, Nullable, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // comment.create
-pub fn create(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        mandela_id: Id,
-        message: String,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateReq {
+    mandela_id: Id,
+    message: String,
+}
 
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult {
     use crate::model::schema::comments;
 
     #[derive(Insertable)]
@@ -72,43 +71,47 @@ pub fn create(mut data: RequestData) -> RequestResult {
 
     telegram_bot::send_admin_message(&comment_message);
 
-    Ok(None)
+    Ok(())
 }
 
 // comment.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::comments;
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    mandela_id: Id,
+    offset: i32,
+    limit: i32,
+}
 
-    #[derive(Deserialize)]
-    struct Req {
-        mandela_id: Id,
-        offset: i32,
-        limit: i32,
-    }
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Comment {
+    #[diesel(sql_type = Int4)]
+    pub id: Id,
+    #[diesel(sql_type = Int4)]
+    pub user_id: Id,
+    #[diesel(sql_type = Text)]
+    pub user_name: String,
+    #[diesel(sql_type = Text)]
+    pub message: String,
+    #[diesel(sql_type = Int8)]
+    pub like_count: i64,
+    #[diesel(sql_type = Int8)]
+    pub dislike_count: i64,
+    #[diesel(sql_type = Nullable<Int2>)]
+    pub like: Option<i16>,
+    #[diesel(sql_type = Timestamptz)]
+    pub create_ts: NaiveDateTime,
+    #[diesel(sql_type = Timestamptz)]
+    pub update_ts: NaiveDateTime,
+}
 
-    let req: Req = data.params()?;
-
-    #[derive(QueryableByName, Serialize)]
-    pub struct Comment {
-        #[diesel(sql_type = Int4)]
-        pub id: Id,
-        #[diesel(sql_type = Int4)]
-        pub user_id: Id,
-        #[diesel(sql_type = Text)]
-        pub user_name: String,
-        #[diesel(sql_type = Text)]
-        pub message: String,
-        #[diesel(sql_type = Int8)]
-        pub like_count: i64,
-        #[diesel(sql_type = Int8)]
-        pub dislike_count: i64,
-        #[diesel(sql_type = Nullable<Int2>)]
-        pub like: Option<i16>,
-        #[diesel(sql_type = Timestamptz)]
-        pub create_ts: NaiveDateTime,
-        #[diesel(sql_type = Timestamptz)]
-        pub update_ts: NaiveDateTime,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    total_count: i64,
+    comments: Vec<Comment>,
+}
+
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
+    use crate::model::schema::comments;
 
     let list = diesel::dsl::sql_query(
         "SELECT c.id, u.id AS user_id, u.name AS user_name, message, l.value AS like, c.create_ts, c.update_ts,
@@ -133,34 +136,25 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .select(diesel::dsl::count_star())
         .first(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        total_count: i64,
-        comments: Vec<Comment>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         total_count,
         comments: list,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // comment.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    message: String,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::comments;
     use crate::model::schema::comments::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        message: String,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = comments)]
     pub struct UpdateComment {
@@ -177,14 +171,12 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_comment)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // comment.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::comments::dsl::*;
-    let req: RequestId = data.params()?;
-
     diesel::delete(comments.filter(id.eq(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
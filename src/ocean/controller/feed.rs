@@ -4,42 +4,47 @@ use chrono::NaiveDateTime;
 use diesel::dsl::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int4, Int8, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // feed.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        limit: i32,
-        offset: i32,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    limit: i32,
+    offset: i32,
+}
 
-    let req: Req = data.params()?;
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Feed {
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int8, column_name = "row")]
+    #[serde(rename = "row")]
+    row_number: i64,
+    #[diesel(sql_type = Int4)]
+    title_id: Id,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Text)]
+    message: String,
+    #[diesel(sql_type = Int4)]
+    user_id: Id,
+    #[diesel(sql_type = Text)]
+    user_name: String,
+    #[diesel(sql_type = Text)]
+    #[serde(rename(serialize = "type"))]
+    type_: String,
+    #[diesel(sql_type = Timestamptz)]
+    create_ts: NaiveDateTime,
+}
 
-    #[derive(QueryableByName, Serialize)]
-    struct Feed {
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int8, column_name = "row")]
-        #[serde(rename = "row")]
-        row_number: i64,
-        #[diesel(sql_type = Int4)]
-        title_id: Id,
-        #[diesel(sql_type = Text)]
-        title: String,
-        #[diesel(sql_type = Text)]
-        message: String,
-        #[diesel(sql_type = Int4)]
-        user_id: Id,
-        #[diesel(sql_type = Text)]
-        user_name: String,
-        #[diesel(sql_type = Text)]
-        #[serde(rename(serialize = "type"))]
-        type_: String,
-        #[diesel(sql_type = Timestamptz)]
-        create_ts: NaiveDateTime,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    feeds: Vec<Feed>,
+    total_count: i64,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     let feeds = sql_query(
         "SELECT c.id, rank() OVER (PARTITION BY mandela_id ORDER BY c.id ASC) AS row, m.id AS title_id,
             (CASE WHEN m.title_mode = 0 THEN m.title ELSE m.what || ': ' || m.before || ' / ' || m.after END) AS title,
@@ -91,12 +96,6 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
     )
     .load::<TotalCount>(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        feeds: Vec<Feed>,
-        total_count: i64,
-    }
-
     let total_count = if !total_counts.is_empty() {
         total_counts[0].mandels_count
             + total_counts[0].comments_count
@@ -106,8 +105,7 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         0
     };
 
-    let resp = Resp { feeds, total_count };
+    let resp = GetAllResp { feeds, total_count };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
@@ -3,40 +3,36 @@ use crate::types::Id;
 use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // forum.category.create
-pub fn create(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_categories;
-    use crate::model::schema::forum_categories::dsl::*;
-
-    #[derive(Insertable, Deserialize)]
-    #[diesel(table_name = forum_categories)]
-    struct Req {
-        name: String,
-        order_index: i16,
-    }
+#[derive(Insertable, Deserialize, JsonSchema)]
+#[diesel(table_name = crate::model::schema::forum_categories)]
+pub struct CreateReq {
+    name: String,
+    order_index: i16,
+}
 
-    let req: Req = data.params()?;
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult {
+    use crate::model::schema::forum_categories::dsl::*;
 
     diesel::insert_into(forum_categories)
         .values(&req)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.category.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_categories::dsl::*;
-
-    let req: RequestId = data.params()?;
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct ForumCategory {
+    name: String,
+    order_index: i16,
+}
 
-    #[derive(Queryable, Serialize)]
-    pub struct ForumCategory {
-        name: String,
-        order_index: i16,
-    }
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<Option<ForumCategory>> {
+    use crate::model::schema::forum_categories::dsl::*;
 
     let forum_category = forum_categories
         .select((name, order_index))
@@ -44,24 +40,21 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
         .first::<ForumCategory>(&mut data.db.conn)
         .optional()?;
 
-    let result = serde_json::to_value(&forum_category)?;
-    Ok(Some(result))
+    Ok(forum_category)
 }
 
 // forum.category.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    name: String,
+    order_index: i16,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::forum_categories;
     use crate::model::schema::forum_categories::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        name: String,
-        order_index: i16,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = forum_categories)]
     pub struct UpdateForumCategory {
@@ -80,14 +73,12 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_forum_category)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.category.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
-
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::forum_categories::dsl::*;
     diesel::delete(forum_categories.filter(id.eq(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
@@ -3,6 +3,7 @@ use crate::types::Id;
 use chrono::NaiveDateTime;
 use diesel::prelude::*;
 use diesel::sql_types::{Int4, Int8, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub mod category;
@@ -10,7 +11,7 @@ pub mod post;
 pub mod section;
 pub mod topic;
 
-#[derive(QueryableByName, Serialize)]
+#[derive(QueryableByName, Serialize, JsonSchema)]
 pub struct Topic {
     #[diesel(sql_type = Int4)]
     id: Id,
@@ -31,14 +32,20 @@ pub struct Topic {
 }
 
 // forum.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_categories;
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct Category {
+    id: Id,
+    name: String,
+}
 
-    #[derive(Queryable, Serialize)]
-    pub struct Category {
-        id: Id,
-        name: String,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    categories: Vec<Category>,
+    sections: Vec<section::Section>,
+}
+
+pub fn get_all(mut data: RequestData, _: ()) -> RequestResult<GetAllResp> {
+    use crate::model::schema::forum_categories;
 
     let categories = forum_categories::table
         .select((forum_categories::id, forum_categories::name))
@@ -47,31 +54,28 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
 
     let sections = section::get_sections(&mut data.db, None)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        categories: Vec<Category>,
-        sections: Vec<section::Section>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         categories,
         sections,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.getNew
-pub fn get_new(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        offset: i32,
-        limit: i32,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetNewReq {
+    offset: i32,
+    limit: i32,
+}
 
-    let req: Req = data.params()?;
+#[derive(Serialize, JsonSchema)]
+pub struct GetNewResp {
+    topic_count: i64,
+    topics: Vec<Topic>,
+}
 
+pub fn get_new(mut data: RequestData, req: GetNewReq) -> RequestResult<GetNewResp> {
     use diesel::prelude::*;
 
     use crate::model::schema::forum_topics::dsl::*;
@@ -83,26 +87,15 @@ pub fn get_new(mut data: RequestData) -> RequestResult {
         .select(diesel::dsl::count_star())
         .first(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        topic_count: i64,
-        topics: Vec<Topic>,
-    }
-
-    let resp = Resp {
+    let resp = GetNewResp {
         topic_count,
         topics: list,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
-pub fn new_topics(
-    db: &mut db::Db,
-    limit: i32,
-    offset: i32,
-) -> Result<Vec<Topic>, Box<dyn std::error::Error>> {
+pub fn new_topics(db: &mut db::Db, limit: i32, offset: i32) -> Result<Vec<Topic>, Error> {
     let result = diesel::dsl::sql_query("
     SELECT ft.id, ft.name, fp.post, fp.id AS post_id, fp.create_ts AS post_create_ts, u.id AS user_id, u.name AS user_name,
         (SELECT count(*) FROM forum_posts WHERE topic_id = ft.id) AS post_count
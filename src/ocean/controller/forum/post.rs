@@ -6,19 +6,53 @@ use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int2, Int4, Int8, Nullable, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // forum.post.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        topic_id: Id,
-        offset: i32,
-        limit: i32,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    topic_id: Id,
+    offset: i32,
+    limit: i32,
+}
 
-    let req: Req = data.params()?;
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Post {
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int4)]
+    user_id: Id,
+    #[diesel(sql_type = Text)]
+    user_name: String,
+    #[diesel(sql_type = Text)]
+    post: String,
+    #[diesel(sql_type = Int8)]
+    like_count: i64,
+    #[diesel(sql_type = Int8)]
+    dislike_count: i64,
+    #[diesel(sql_type = Nullable<Int2>)]
+    like: Option<i16>,
+    #[diesel(sql_type = Timestamptz)]
+    create_ts: NaiveDateTime,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    category_id: Id,
+    category_name: String,
+    section_id: Id,
+    section_name: String,
+    topic_name: String,
+    topic_type: i16,
+    topic_user_id: Id,
+    poll_selection_type: Option<i16>,
+    poll: Option<Vec<topic::Poll>>,
+    post_count: i64,
+    posts: Vec<Post>,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     #[derive(Queryable)]
     struct TopicMeta {
         category_id: Id,
@@ -54,26 +88,6 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .filter(forum_topics::id.eq(req.topic_id))
         .first::<TopicMeta>(&mut data.db.conn)?;
 
-    #[derive(QueryableByName, Serialize)]
-    struct Post {
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int4)]
-        user_id: Id,
-        #[diesel(sql_type = Text)]
-        user_name: String,
-        #[diesel(sql_type = Text)]
-        post: String,
-        #[diesel(sql_type = Int8)]
-        like_count: i64,
-        #[diesel(sql_type = Int8)]
-        dislike_count: i64,
-        #[diesel(sql_type = Nullable<Int2>)]
-        like: Option<i16>,
-        #[diesel(sql_type = Timestamptz)]
-        create_ts: NaiveDateTime,
-    }
-
     let list = diesel::sql_query(
         "SELECT fp.id, u.id AS user_id, u.name AS user_name, post, l.value AS like, fp.create_ts,
             (SELECT count(*) FROM likes WHERE post_id = fp.id AND value = 0) AS like_count,
@@ -104,22 +118,7 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         poll = Some(answers);
     }
 
-    #[derive(Serialize)]
-    struct Resp {
-        category_id: Id,
-        category_name: String,
-        section_id: Id,
-        section_name: String,
-        topic_name: String,
-        topic_type: i16,
-        topic_user_id: Id,
-        poll_selection_type: Option<i16>,
-        poll: Option<Vec<topic::Poll>>,
-        post_count: i64,
-        posts: Vec<Post>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         category_id: topic_meta.category_id,
         category_name: topic_meta.category_name,
         section_id: topic_meta.section_id,
@@ -133,21 +132,18 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         posts: list,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.post.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_posts::dsl::*;
-
-    let req: RequestId = data.params()?;
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct ForumPost {
+    topic_id: Id,
+    post: String,
+}
 
-    #[derive(Queryable, Serialize)]
-    pub struct ForumPost {
-        topic_id: Id,
-        post: String,
-    }
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<Option<ForumPost>> {
+    use crate::model::schema::forum_posts::dsl::*;
 
     let forum_post = forum_posts
         .select((topic_id, post))
@@ -155,23 +151,20 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
         .first::<ForumPost>(&mut data.db.conn)
         .optional()?;
 
-    let result = serde_json::to_value(&forum_post)?;
-    Ok(Some(result))
+    Ok(forum_post)
 }
 
 // forum.post.create
-pub fn create(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateReq {
+    topic_id: Id,
+    post: String,
+}
+
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult<ResponseId> {
     use crate::model::schema::forum_posts;
     use crate::model::schema::forum_posts::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        topic_id: Id,
-        post: String,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(Insertable)]
     #[diesel(table_name = forum_posts)]
     struct NewForumPost<'a> {
@@ -230,23 +223,20 @@ pub fn create(mut data: RequestData) -> RequestResult {
     telegram_bot::send_admin_message(&post_message);
 
     let resp = ResponseId { id: post_id };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.post.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    post: String,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::forum_posts;
     use crate::model::schema::forum_posts::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        post: String,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = forum_posts)]
     pub struct UpdateForumPost {
@@ -263,13 +253,11 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_forum_post)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.post.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
-
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::forum_posts;
 
     let topic_id = forum_posts::table
@@ -302,5 +290,5 @@ pub fn delete(mut data: RequestData) -> RequestResult {
     topic::update_last_post(&mut data.db, topic_id, prev_post_id, prev_post_create_ts)?;
     diesel::delete(forum_posts::table.filter(forum_posts::id.eq(req.id)))
         .execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
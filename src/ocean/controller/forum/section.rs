@@ -4,9 +4,10 @@ use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int4, Int8, Text};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(QueryableByName, Serialize)]
+#[derive(QueryableByName, Serialize, JsonSchema)]
 pub struct Section {
     #[diesel(sql_type = Int4)]
     id: Id,
@@ -20,10 +21,7 @@ pub struct Section {
     post_count: i64,
 }
 
-pub fn get_sections(
-    db: &mut db::Db,
-    category_id: Option<Id>,
-) -> Result<Vec<Section>, Box<dyn std::error::Error>> {
+pub fn get_sections(db: &mut db::Db, category_id: Option<Id>) -> Result<Vec<Section>, Error> {
     let mut result = diesel::dsl::sql_query(
         "SELECT fs.id, fs.name, fs.category_id,
 	        (SELECT COUNT(*) FROM forum_topics WHERE section_id = fs.id) AS topic_count,
@@ -37,24 +35,25 @@ pub fn get_sections(
 
     if let Some(id) = category_id {
         // Expensive but simple
-        result = result
-            .into_iter()
-            .filter(|section| section.category_id == id)
-            .collect();
+        result.retain(|section| section.category_id == id);
     }
 
     Ok(result)
 }
 
 // forum.section.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        category_id: Id,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    category_id: Id,
+}
 
-    let req: Req = data.params()?;
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    category_name: String,
+    sections: Vec<Section>,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     use crate::model::schema::forum_categories;
 
     let category_name = forum_categories::table
@@ -64,33 +63,24 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
 
     let sections = get_sections(&mut data.db, Some(req.category_id))?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        category_name: String,
-        sections: Vec<Section>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         category_name,
         sections,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.section.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_sections::dsl::*;
-
-    let req: RequestId = data.params()?;
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct ForumSection {
+    category_id: Id,
+    name: String,
+    order_index: i16,
+}
 
-    #[derive(Queryable, Serialize)]
-    pub struct ForumSection {
-        category_id: Id,
-        name: String,
-        order_index: i16,
-    }
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<Option<ForumSection>> {
+    use crate::model::schema::forum_sections::dsl::*;
 
     let forum_section = forum_sections
         .select((category_id, name, order_index))
@@ -98,46 +88,40 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
         .first::<ForumSection>(&mut data.db.conn)
         .optional()?;
 
-    let result = serde_json::to_value(&forum_section)?;
-    Ok(Some(result))
+    Ok(forum_section)
 }
 
 // forum.section.create
-pub fn create(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_sections;
-    use crate::model::schema::forum_sections::dsl::*;
-
-    #[derive(Insertable, Deserialize)]
-    #[diesel(table_name = forum_sections)]
-    struct Req {
-        category_id: Id,
-        name: String,
-        order_index: i16,
-    }
+#[derive(Insertable, Deserialize, JsonSchema)]
+#[diesel(table_name = crate::model::schema::forum_sections)]
+pub struct CreateReq {
+    category_id: Id,
+    name: String,
+    order_index: i16,
+}
 
-    let req: Req = data.params()?;
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult {
+    use crate::model::schema::forum_sections::dsl::*;
 
     diesel::insert_into(forum_sections)
         .values(&req)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.section.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    name: String,
+    order_index: i16,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::forum_sections;
     use crate::model::schema::forum_sections::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        name: String,
-        order_index: i16,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = forum_sections)]
     pub struct UpdateForumSection {
@@ -156,15 +140,13 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_forum_section)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.section.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
-
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::forum_sections::dsl::*;
 
     diesel::delete(forum_sections.filter(id.eq(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
@@ -4,12 +4,13 @@ use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Bool, Int2, Int4, Int8, Nullable, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 pub const COMMON_TOPIC_TYPE: i16 = 0;
 pub const POLL_TOPIC_TYPE: i16 = 1;
 
-#[derive(Serialize, QueryableByName)]
+#[derive(Serialize, QueryableByName, JsonSchema)]
 pub struct Poll {
     #[diesel(sql_type = Int4)]
     id: Id,
@@ -22,16 +23,46 @@ pub struct Poll {
 }
 
 // forum.topic.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        section_id: Id,
-        offset: i64,
-        limit: i64,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    section_id: Id,
+    offset: i64,
+    limit: i64,
+}
+
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Topic {
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int4)]
+    user_id: Id,
+    #[diesel(sql_type = Text)]
+    user_name: String,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[serde(rename(serialize = "type"))]
+    #[diesel(sql_type = Int2)]
+    type_: i16,
+    #[diesel(sql_type = Timestamptz)]
+    create_ts: NaiveDateTime,
+    #[diesel(sql_type = Nullable<Int4>)]
+    last_post_id: Option<Id>,
+    #[diesel(sql_type = Nullable<Timestamptz>)]
+    last_post_create_ts: Option<NaiveDateTime>,
+    #[diesel(sql_type = Int8)]
+    post_count: i64,
+}
 
-    let req: Req = data.params()?;
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    category_id: Id,
+    category_name: String,
+    section_name: String,
+    topic_count: i64,
+    topics: Vec<Topic>,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     #[derive(Queryable)]
     struct SectionMeta {
         category_id: Id,
@@ -55,29 +86,6 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .filter(forum_sections::id.eq(req.section_id))
         .first::<SectionMeta>(&mut data.db.conn)?;
 
-    #[derive(QueryableByName, Serialize)]
-    struct Topic {
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int4)]
-        user_id: Id,
-        #[diesel(sql_type = Text)]
-        user_name: String,
-        #[diesel(sql_type = Text)]
-        name: String,
-        #[serde(rename(serialize = "type"))]
-        #[diesel(sql_type = Int2)]
-        type_: i16,
-        #[diesel(sql_type = Timestamptz)]
-        create_ts: NaiveDateTime,
-        #[diesel(sql_type = Nullable<Int4>)]
-        last_post_id: Option<Id>,
-        #[diesel(sql_type = Nullable<Timestamptz>)]
-        last_post_create_ts: Option<NaiveDateTime>,
-        #[diesel(sql_type = Int8)]
-        post_count: i64,
-    }
-
     let topics = diesel::dsl::sql_query(
         "
     SELECT ft.id, ft.user_id, ft.last_post_id, ft.last_post_create_ts, ft.name, ft.type AS type_, ft.create_ts, u.name AS user_name,
@@ -99,16 +107,7 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .select(diesel::dsl::count_star())
         .first(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        category_id: Id,
-        category_name: String,
-        section_name: String,
-        topic_count: i64,
-        topics: Vec<Topic>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         category_id: section_meta.category_id,
         category_name: section_meta.category_name,
         section_name: section_meta.section_name,
@@ -116,22 +115,19 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         topics,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.topic.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::forum_topics::dsl::*;
-
-    let req: RequestId = data.params()?;
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct ForumTopic {
+    user_id: Id,
+    section_id: Id,
+    name: String,
+}
 
-    #[derive(Queryable, Serialize)]
-    pub struct ForumTopic {
-        user_id: Id,
-        section_id: Id,
-        name: String,
-    }
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<Option<ForumTopic>> {
+    use crate::model::schema::forum_topics::dsl::*;
 
     let forum_topic = forum_topics
         .select((user_id, section_id, name))
@@ -139,27 +135,24 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
         .first::<ForumTopic>(&mut data.db.conn)
         .optional()?;
 
-    let result = serde_json::to_value(&forum_topic)?;
-    Ok(Some(result))
+    Ok(forum_topic)
 }
 
 // forum.topic.create
-pub fn create(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, Clone, JsonSchema)]
+pub struct CreateReq {
+    section_id: Id,
+    name: String,
+    #[serde(rename(deserialize = "type"))]
+    topic_type: i16,
+    poll_answers: Option<Vec<String>>,
+    poll_answer_selection: Option<i16>,
+}
+
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult<ResponseId> {
     use crate::model::schema::forum_topics;
     use crate::model::schema::forum_topics::dsl::*;
 
-    #[derive(Deserialize, Clone)]
-    struct Req {
-        section_id: Id,
-        name: String,
-        #[serde(rename(deserialize = "type"))]
-        topic_type: i16,
-        poll_answers: Option<Vec<String>>,
-        poll_answer_selection: Option<i16>,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(Insertable)]
     #[diesel(table_name = forum_topics)]
     struct NewForumTopic {
@@ -206,23 +199,20 @@ pub fn create(mut data: RequestData) -> RequestResult {
     })?;
 
     let resp = ResponseId { id: topic_id };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // forum.topic.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    name: String,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::forum_topics;
     use crate::model::schema::forum_topics::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        name: String,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = forum_topics)]
     pub struct UpdateForumTopic {
@@ -239,16 +229,14 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_forum_topic)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // forum.topic.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
-
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::forum_topics::dsl::*;
     diesel::delete(forum_topics.filter(id.eq(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
 
 pub fn update_last_post(
@@ -256,7 +244,7 @@ pub fn update_last_post(
     id: Id,
     post_id: Option<Id>,
     post_create_ts: Option<NaiveDateTime>,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<(), Error> {
     use crate::model::schema::forum_topics;
 
     #[derive(AsChangeset)]
@@ -280,14 +268,18 @@ pub fn update_last_post(
 }
 
 // forum.topic.vote
-pub fn vote(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        votes: Vec<Id>,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct VoteReq {
+    id: Id,
+    votes: Vec<Id>,
+}
 
-    let req: Req = data.params()?;
+#[derive(Serialize, JsonSchema)]
+pub struct VoteResp {
+    poll: Vec<Poll>,
+}
+
+pub fn vote(mut data: RequestData, req: VoteReq) -> RequestResult<VoteResp> {
     let conn = &mut data.db.conn;
     let poll_user_id = data.user.id;
     let poll_topic_id = req.id;
@@ -322,14 +314,8 @@ pub fn vote(mut data: RequestData) -> RequestResult {
 
     let poll = get_poll(&mut data.db, poll_topic_id, poll_user_id);
 
-    #[derive(Serialize)]
-    struct Resp {
-        poll: Vec<Poll>,
-    }
-
-    let resp = Resp { poll };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    let resp = VoteResp { poll };
+    Ok(resp)
 }
 
 pub fn get_poll(db: &mut db::Db, topic_id: Id, user_id: Id) -> Vec<Poll> {
@@ -353,21 +339,14 @@ pub fn get_poll(db: &mut db::Db, topic_id: Id, user_id: Id) -> Vec<Poll> {
 }
 
 // forum.topic.getVoteUsers
-pub fn get_vote_users(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-    }
-
-    let req: Req = data.params()?;
-
-    #[derive(Queryable, Serialize)]
-    struct VoteUser {
-        id: Id,
-        name: String,
-        answer_id: Id,
-    }
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct VoteUser {
+    id: Id,
+    name: String,
+    answer_id: Id,
+}
 
+pub fn get_vote_users(mut data: RequestData, req: RequestId) -> RequestResult<Vec<VoteUser>> {
     use crate::model::schema::forum_poll_votes::dsl::*;
     use crate::model::schema::users;
 
@@ -378,6 +357,5 @@ pub fn get_vote_users(mut data: RequestData) -> RequestResult {
         .order((answer_id.asc(), users::name.asc()))
         .load::<VoteUser>(&mut data.db.conn)?;
 
-    let result = serde_json::to_value(&vote_users)?;
-    Ok(Some(result))
+    Ok(vote_users)
 }
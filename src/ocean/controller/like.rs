@@ -1,22 +1,21 @@
 use crate::controller::*;
 use crate::types::Id;
 use diesel::prelude::*;
+use schemars::JsonSchema;
 use serde::Deserialize;
 
 // like.create
-pub fn create(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateReq {
+    comment_id: Option<Id>,
+    post_id: Option<Id>,
+    action: i16,
+}
+
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult {
     use crate::model::schema::likes;
     use crate::model::schema::likes::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        comment_id: Option<Id>,
-        post_id: Option<Id>,
-        action: i16,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(Insertable, Deserialize)]
     #[diesel(table_name = likes)]
     struct NewLike {
@@ -37,20 +36,18 @@ pub fn create(mut data: RequestData) -> RequestResult {
         .values(&new_like)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // like.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::likes::dsl::*;
-
-    #[derive(Deserialize)]
-    struct Req {
-        comment_id: Option<Id>,
-        post_id: Option<Id>,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteReq {
+    comment_id: Option<Id>,
+    post_id: Option<Id>,
+}
 
-    let req: Req = data.params()?;
+pub fn delete(mut data: RequestData, req: DeleteReq) -> RequestResult {
+    use crate::model::schema::likes::dsl::*;
 
     if let Some(like_comment_id) = req.comment_id {
         diesel::delete(likes.filter(comment_id.eq(like_comment_id).and(user_id.eq(data.user.id))))
@@ -62,26 +59,24 @@ pub fn delete(mut data: RequestData) -> RequestResult {
             .execute(&mut data.db.conn)?;
     }
 
-    Ok(None)
+    Ok(())
 }
 
 // like.getUsers
-pub fn get_users(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        comment_id: Option<Id>,
-        post_id: Option<Id>,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct GetUsersReq {
+    comment_id: Option<Id>,
+    post_id: Option<Id>,
+}
 
-    #[derive(Queryable, Serialize)]
-    struct LikeUser {
-        id: Id,
-        name: String,
-        action: i16,
-    }
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct LikeUser {
+    id: Id,
+    name: String,
+    action: i16,
+}
 
+pub fn get_users(mut data: RequestData, req: GetUsersReq) -> RequestResult<Vec<LikeUser>> {
     use crate::model::schema::likes::dsl::*;
     use crate::model::schema::users;
 
@@ -102,6 +97,5 @@ pub fn get_users(mut data: RequestData) -> RequestResult {
         .order((value.asc(), users::name.asc()))
         .load::<LikeUser>(&mut data.db.conn)?;
 
-    let result = serde_json::to_value(&like_users)?;
-    Ok(Some(result))
+    Ok(like_users)
 }
@@ -6,6 +6,7 @@ use chrono::NaiveDateTime;
 use chrono::prelude::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int2, Int4, Int8, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable)]
@@ -18,15 +19,15 @@ pub struct MandelaTitle {
     pub after: String,
 }
 
-#[derive(QueryableByName, Serialize)]
-struct Votes {
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Votes {
     #[diesel(sql_type = Int2)]
     vote: i16,
     #[diesel(sql_type = Int8)]
     count: i64,
 }
 
-#[derive(QueryableByName, Serialize)]
+#[derive(QueryableByName, Serialize, JsonSchema)]
 pub struct Comment {
     #[diesel(sql_type = Int4)]
     id: Id,
@@ -102,24 +103,22 @@ fn update_categories(
             .execute(conn)?;
     }
 
-    Ok(None)
+    Ok(())
 }
 
 // mandela.create
-pub fn create(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        title_mode: i32,
-        title: String,
-        what: String,
-        before: String,
-        after: String,
-        description: String,
-        categories: serde_json::Value,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateReq {
+    title_mode: i32,
+    title: String,
+    what: String,
+    before: String,
+    after: String,
+    description: String,
+    categories: Vec<i16>,
+}
 
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult<ResponseId> {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
 
@@ -150,8 +149,7 @@ pub fn create(mut data: RequestData) -> RequestResult {
         .returning(id)
         .get_result::<Id>(&mut data.db.conn)?;
 
-    let category_numbers: Vec<i16> = serde_json::from_value(req.categories).unwrap();
-    update_categories(&mut data.db.conn, mandela_id, category_numbers)?;
+    update_categories(&mut data.db.conn, mandela_id, req.categories)?;
 
     let mut message = format_mandela_title(mandela::MandelaTitle {
         id: mandela_id,
@@ -173,27 +171,25 @@ pub fn create(mut data: RequestData) -> RequestResult {
     telegram_bot::send_message(&message);
 
     let resp = ResponseId { id: mandela_id };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // mandela.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    title_mode: i32,
+    title: String,
+    what: String,
+    before: String,
+    after: String,
+    description: String,
+    categories: Vec<i16>,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        title_mode: i32,
-        title: String,
-        what: String,
-        before: String,
-        after: String,
-        description: String,
-        categories: serde_json::Value,
-    }
-
-    let req: Req = data.params()?;
 
     #[derive(AsChangeset)]
     #[diesel(table_name = mandels)]
@@ -221,10 +217,9 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_mandela)
         .execute(&mut data.db.conn)?;
 
-    let category_numbers: Vec<i16> = serde_json::from_value(req.categories).unwrap();
-    update_categories(&mut data.db.conn, req.id, category_numbers)?;
+    update_categories(&mut data.db.conn, req.id, req.categories)?;
 
-    Ok(None)
+    Ok(())
 }
 
 fn get_poll(db: &mut db::Db, mandela_id: Id) -> Vec<Votes> {
@@ -242,7 +237,33 @@ fn get_poll(db: &mut db::Db, mandela_id: Id) -> Vec<Votes> {
 }
 
 // mandela.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct Mandela {
+    id: Id,
+    title: String,
+    title_mode: i32,
+    description: String,
+    user_id: Id,
+    user_name: String,
+    create_ts: NaiveDateTime,
+    update_ts: NaiveDateTime,
+    what: String,
+    before: String,
+    after: String,
+    mark_ts: Option<NaiveDateTime>,
+    trash: bool,
+    automatic_trash: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GetOneResp {
+    mandela: Mandela,
+    votes: Vec<Votes>,
+    vote: Option<i16>,
+    categories: Vec<i16>,
+}
+
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<GetOneResp> {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
     use crate::model::schema::marks;
@@ -250,26 +271,6 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
 
-    let req: RequestId = data.params()?;
-
-    #[derive(Queryable, Serialize)]
-    pub struct Mandela {
-        id: Id,
-        title: String,
-        title_mode: i32,
-        description: String,
-        user_id: Id,
-        user_name: String,
-        create_ts: NaiveDateTime,
-        update_ts: NaiveDateTime,
-        what: String,
-        before: String,
-        after: String,
-        mark_ts: Option<NaiveDateTime>,
-        trash: bool,
-        automatic_trash: bool,
-    }
-
     let mandela_record = mandels
         .inner_join(users)
         .left_join(
@@ -301,7 +302,7 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
     if let Ok(m) = mandela_record {
         mandela = m;
     } else {
-        return Err(api::make_error(api::error::RECORD_NOT_FOUND));
+        return Err(api::make_error(api::error::RECORD_NOT_FOUND).into());
     }
 
     let mandela_votes = get_poll(&mut data.db, req.id);
@@ -331,27 +332,56 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
         .filter(categories::mandela_id.eq(req.id))
         .load(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct MandelaResp {
-        mandela: Mandela,
-        votes: Vec<Votes>,
-        vote: Option<i16>,
-        categories: Vec<i16>,
-    }
-
-    let resp = MandelaResp {
+    let resp = GetOneResp {
         mandela,
         votes: mandela_votes,
         vote: mandela_vote,
         categories: category_numbers,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // mandela.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    offset: i64,
+    limit: i64,
+    user_id: Option<Id>,
+    filter: Option<i8>,
+    category: Option<i16>,
+    sort: i8,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct MandelaResp {
+    id: Id,
+    title_mode: i32,
+    title: String,
+    what: String,
+    before: String,
+    after: String,
+    create_ts: NaiveDateTime,
+    user_name: Option<String>,
+    user_id: Id,
+    comment_count: i64,
+    mark_ts: Option<NaiveDateTime>,
+    votes: Vec<Votes>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    total_count: i64,
+    new_count: i64,
+    mine_count: i64,
+    poll_count: i64,
+    trash_count: i64,
+    category_count: i64,
+    user_count: i64,
+    mandels: Vec<MandelaResp>,
+}
+
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     use crate::model::schema::categories;
     use crate::model::schema::categories::dsl::*;
     use crate::model::schema::comments;
@@ -366,18 +396,6 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
     use crate::model::schema::votes::dsl::*;
     use diesel::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        offset: i64,
-        limit: i64,
-        user_id: Option<Id>,
-        filter: Option<i8>,
-        category: Option<i16>,
-        sort: i8,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(Queryable)]
     struct Mandela {
         id: Id,
@@ -471,22 +489,6 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .limit(req.limit)
         .load::<Mandela>(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct MandelaResp {
-        id: Id,
-        title_mode: i32,
-        title: String,
-        what: String,
-        before: String,
-        after: String,
-        create_ts: NaiveDateTime,
-        user_name: Option<String>,
-        user_id: Id,
-        comment_count: i64,
-        mark_ts: Option<NaiveDateTime>,
-        votes: Vec<Votes>,
-    }
-
     let mut mandels_resp: Vec<MandelaResp> = Vec::new();
 
     for elem in list {
@@ -577,19 +579,7 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
             .first(&mut data.db.conn)?;
     }
 
-    #[derive(Serialize)]
-    struct Resp {
-        total_count: i64,
-        new_count: i64,
-        mine_count: i64,
-        poll_count: i64,
-        trash_count: i64,
-        category_count: i64,
-        user_count: i64,
-        mandels: Vec<MandelaResp>,
-    }
-
-    let resp = Resp {
+    let resp = GetAllResp {
         total_count,
         new_count,
         mine_count,
@@ -600,29 +590,24 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         mandels: mandels_resp,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // mandela.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Vec<i32>,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct DeleteReq {
+    id: Vec<i32>,
+}
 
+pub fn delete(mut data: RequestData, req: DeleteReq) -> RequestResult {
     use crate::model::schema::mandels::dsl::*;
 
     diesel::delete(mandels.filter(id.eq_any(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
 
 // mandela.mark
-pub fn mark(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
-
+pub fn mark(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::marks;
     use crate::model::schema::marks::dsl::*;
 
@@ -641,19 +626,17 @@ pub fn mark(mut data: RequestData) -> RequestResult {
     diesel::insert_into(marks)
         .values(&new_mark)
         .execute(&mut data.db.conn)?;
-    Ok(None)
+    Ok(())
 }
 
 // mandela.vote
-pub fn vote(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        vote: i16,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct VoteReq {
+    id: Id,
+    vote: i16,
+}
 
+pub fn vote(mut data: RequestData, req: VoteReq) -> RequestResult<Vec<Votes>> {
     #[derive(Insertable, AsChangeset)]
     #[diesel(table_name = votes)]
     pub struct NewVote {
@@ -688,26 +671,18 @@ pub fn vote(mut data: RequestData) -> RequestResult {
     }
 
     let votes_count = get_poll(&mut data.db, req.id);
-    let result = serde_json::to_value(&votes_count)?;
-    Ok(Some(result))
+    Ok(votes_count)
 }
 
 // mandela.getVoteUsers
-pub fn get_vote_users(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-    }
-
-    let req: Req = data.params()?;
-
-    #[derive(Queryable, Serialize)]
-    struct VoteUser {
-        id: Id,
-        name: String,
-        vote: i16,
-    }
+#[derive(Queryable, Serialize, JsonSchema)]
+pub struct VoteUser {
+    id: Id,
+    name: String,
+    vote: i16,
+}
 
+pub fn get_vote_users(mut data: RequestData, req: RequestId) -> RequestResult<Vec<VoteUser>> {
     use crate::model::schema::users;
     use crate::model::schema::votes::dsl::*;
 
@@ -718,15 +693,10 @@ pub fn get_vote_users(mut data: RequestData) -> RequestResult {
         .order((vote.asc(), users::name.asc()))
         .load::<VoteUser>(&mut data.db.conn)?;
 
-    let result = serde_json::to_value(&vote_users)?;
-    Ok(Some(result))
+    Ok(vote_users)
 }
 
-pub fn new_comments(
-    db: &mut db::Db,
-    limit: i32,
-    offset: i32,
-) -> Result<Vec<Comment>, Box<dyn std::error::Error>> {
+pub fn new_comments(db: &mut db::Db, limit: i32, offset: i32) -> Result<Vec<Comment>, Error> {
     let result = diesel::dsl::sql_query(
         "SELECT id, mandela_id, title_mode, title, what, before, after, message, user_id, user_name, create_ts, comment_count
         FROM (SELECT m.id AS mandela_id, m.title_mode, m.title, m.what, m.before, m.after, c.id, c.message, c.user_id, u.name AS user_name, c.create_ts,
@@ -748,16 +718,14 @@ pub fn new_comments(
 }
 
 // mandela.updateTrash
-pub fn update_trash(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        trash: bool,
-        automatic_trash: bool,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateTrashReq {
+    id: Id,
+    trash: bool,
+    automatic_trash: bool,
+}
 
+pub fn update_trash(mut data: RequestData, req: UpdateTrashReq) -> RequestResult {
     use crate::model::schema::mandels;
     use crate::model::schema::mandels::dsl::*;
 
@@ -765,5 +733,5 @@ pub fn update_trash(mut data: RequestData) -> RequestResult {
         .set((trash.eq(req.trash), automatic_trash.eq(req.automatic_trash)))
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    Ok(())
 }
@@ -1,8 +1,12 @@
+use crate::api;
+use crate::api::user_cache;
 use crate::config;
 use crate::db;
 use crate::types;
+use schemars::JsonSchema;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::any::TypeId;
 
 pub mod activity;
 pub mod comment;
@@ -10,61 +14,156 @@ pub mod feed;
 pub mod forum;
 pub mod like;
 pub mod mandela;
+pub mod ownership;
 pub mod rating;
 pub mod search;
 pub mod user;
 
-pub type RequestResult = Result<Option<serde_json::Value>, Box<dyn std::error::Error>>;
-pub type RequestHandler = fn(RequestData) -> RequestResult;
+pub type RequestResult<R = ()> = Result<R, Error>;
 
-#[derive(Deserialize)]
+// Handler with params and result in JSON, made from typed handler by `call`
+pub type RequestHandler = fn(RequestData) -> RequestResult<Option<serde_json::Value>>;
+
+#[derive(Deserialize, JsonSchema)]
 pub struct RequestId {
     id: types::Id,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, JsonSchema)]
 pub struct ResponseId {
     id: types::Id,
 }
 
 #[derive(Debug)]
-pub struct Error {
-    message: String,
+pub enum Error {
+    Api(api::Error),
+    Params(String, String), // serde path and message
+    Database(diesel::result::Error),
+    Internal(Box<dyn std::error::Error>),
+}
+
+impl Error {
+    // Errors that client can handle are mapped to API codes, the rest are internal
+    pub fn api_error(&self) -> Option<api::Error> {
+        use diesel::result::DatabaseErrorKind;
+        use diesel::result::Error::{DatabaseError, NotFound};
+
+        match self {
+            Error::Api(e) => Some(e.clone()),
+            Error::Params(path, message) => Some(api::Error::new(
+                api::error::INVALID_PARAMETER,
+                Some(format!("{}: {}", path, message)),
+            )),
+            Error::Database(NotFound) => Some(api::Error::new(api::error::RECORD_NOT_FOUND, None)),
+            Error::Database(DatabaseError(DatabaseErrorKind::UniqueViolation, info)) => {
+                Some(api::Error::new(
+                    api::error::CONFLICT,
+                    info.constraint_name().map(String::from),
+                ))
+            }
+            Error::Database(DatabaseError(DatabaseErrorKind::ForeignKeyViolation, info)) => {
+                Some(api::Error::new(
+                    api::error::INVALID_REFERENCE,
+                    info.constraint_name().map(String::from),
+                ))
+            }
+            Error::Database(_) | Error::Internal(_) => None,
+        }
+    }
 }
 
 impl std::error::Error for Error {}
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Controller error: {}", self.message)
+        match self {
+            Error::Api(e) => write!(f, "{}", e),
+            Error::Params(path, message) => write!(f, "Invalid parameter {}: {}", path, message),
+            Error::Database(e) => write!(f, "Database error: {}", e),
+            Error::Internal(e) => write!(f, "Controller error: {}", e),
+        }
     }
 }
 
-impl Error {
-    pub fn new(message: &str) -> Self {
-        Error {
-            message: message.to_string(),
-        }
+impl From<api::Error> for Error {
+    fn from(e: api::Error) -> Self {
+        Error::Api(e)
+    }
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(e: diesel::result::Error) -> Self {
+        Error::Database(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Internal(Box::new(e))
     }
 }
 
 pub struct RequestData {
     db: db::Db,
     user: types::User,
+    session_id: types::Id,
     params: Option<serde_json::Value>,
 }
 
 impl RequestData {
-    pub fn new(db: db::Db, user: types::User, params: Option<serde_json::Value>) -> Self {
-        Self { db, user, params }
+    pub fn new(
+        db: db::Db,
+        session: user_cache::Session,
+        params: Option<serde_json::Value>,
+    ) -> Self {
+        Self {
+            db,
+            user: session.user,
+            session_id: session.id,
+            params,
+        }
     }
 
-    pub fn params<T: DeserializeOwned>(&self) -> Result<T, Box<dyn std::error::Error>> {
-        if let Some(p) = &self.params {
-            Ok(serde_json::from_value::<T>((*p).clone())?)
-        } else {
-            Err(Box::new(Error::new("Parameters is absent")))
-        }
+    pub fn params<T: DeserializeOwned>(&self) -> Result<T, Error> {
+        parse_params(self.params.as_ref())
+    }
+}
+
+fn parse_params<T: DeserializeOwned>(params: Option<&serde_json::Value>) -> Result<T, Error> {
+    if let Some(p) = params {
+        serde_path_to_error::deserialize(p.clone()).map_err(|e| {
+            let path = e.path().to_string();
+            Error::Params(path, e.into_inner().to_string())
+        })
+    } else {
+        Err(Error::Api(api::Error::new(
+            api::error::PARAMETER_NOT_FOUND,
+            None,
+        )))
+    }
+}
+
+// Methods without params ignore them, methods without result return none
+pub fn call<P, R>(
+    data: RequestData,
+    handler: fn(RequestData, P) -> RequestResult<R>,
+) -> RequestResult<Option<serde_json::Value>>
+where
+    P: DeserializeOwned + 'static,
+    R: Serialize + 'static,
+{
+    let params = if TypeId::of::<P>() == TypeId::of::<()>() {
+        serde_json::from_value(serde_json::Value::Null)?
+    } else {
+        data.params()?
+    };
+
+    let result = handler(data, params)?;
+
+    if TypeId::of::<R>() == TypeId::of::<()>() {
+        Ok(None)
+    } else {
+        Ok(Some(serde_json::to_value(&result)?))
     }
 }
 
@@ -88,6 +187,142 @@ pub fn format_mandela_title(mandela_title: mandela::MandelaTitle) -> String {
     )
 }
 
-pub fn ping(_data: RequestData) -> RequestResult {
-    Ok(None)
+pub fn ping(_data: RequestData, _: ()) -> RequestResult {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diesel::result::{DatabaseErrorInformation, DatabaseErrorKind};
+    use serde_json::json;
+
+    struct Info {
+        constraint: &'static str,
+    }
+
+    impl DatabaseErrorInformation for Info {
+        fn message(&self) -> &str {
+            "violation"
+        }
+
+        fn details(&self) -> Option<&str> {
+            None
+        }
+
+        fn hint(&self) -> Option<&str> {
+            None
+        }
+
+        fn table_name(&self) -> Option<&str> {
+            None
+        }
+
+        fn column_name(&self) -> Option<&str> {
+            None
+        }
+
+        fn constraint_name(&self) -> Option<&str> {
+            Some(self.constraint)
+        }
+
+        fn statement_position(&self) -> Option<i32> {
+            None
+        }
+    }
+
+    fn database_error(kind: DatabaseErrorKind, constraint: &'static str) -> Error {
+        Error::Database(diesel::result::Error::DatabaseError(
+            kind,
+            Box::new(Info { constraint }),
+        ))
+    }
+
+    fn code_and_data(err: &Error) -> Option<(api::error::ErrorCode, Option<String>)> {
+        err.api_error().map(|e| (e.code(), e.data()))
+    }
+
+    #[test]
+    fn database_errors() {
+        let not_found = Error::Database(diesel::result::Error::NotFound);
+        assert_eq!(
+            code_and_data(&not_found),
+            Some((api::error::RECORD_NOT_FOUND, None))
+        );
+
+        let unique = database_error(DatabaseErrorKind::UniqueViolation, "users_name_key");
+        assert_eq!(
+            code_and_data(&unique),
+            Some((api::error::CONFLICT, Some("users_name_key".to_string())))
+        );
+
+        let foreign_key = database_error(
+            DatabaseErrorKind::ForeignKeyViolation,
+            "comments_user_id_fkey",
+        );
+        assert_eq!(
+            code_and_data(&foreign_key),
+            Some((
+                api::error::INVALID_REFERENCE,
+                Some("comments_user_id_fkey".to_string())
+            ))
+        );
+
+        let other = database_error(DatabaseErrorKind::CheckViolation, "votes_vote_check");
+        assert!(other.api_error().is_none());
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Item {
+        id: types::Id,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct ItemsReq {
+        items: Vec<Item>,
+    }
+
+    #[test]
+    fn params_error_has_path() {
+        let params = json!({ "items": [{ "id": 1 }, { "id": "2" }] });
+        let err = parse_params::<ItemsReq>(Some(&params)).unwrap_err();
+        let (code, data) = code_and_data(&err).unwrap();
+
+        assert_eq!(code, api::error::INVALID_PARAMETER);
+        assert!(data.unwrap().starts_with("items[1].id: invalid type"));
+    }
+
+    #[test]
+    fn nested_params_are_typed() {
+        let params = json!({
+            "title_mode": 0,
+            "title": "",
+            "what": "",
+            "before": "",
+            "after": "",
+            "description": "",
+            "categories": [1, "2"],
+        });
+        let err = parse_params::<mandela::CreateReq>(Some(&params)).err().unwrap();
+        let (code, data) = code_and_data(&err).unwrap();
+
+        assert_eq!(code, api::error::INVALID_PARAMETER);
+        assert!(data.unwrap().starts_with("categories[1]: invalid type"));
+    }
+
+    #[test]
+    fn params_not_found() {
+        let err = parse_params::<ItemsReq>(None).unwrap_err();
+        assert_eq!(
+            code_and_data(&err),
+            Some((api::error::PARAMETER_NOT_FOUND, None))
+        );
+    }
+
+    #[test]
+    fn params_parsed() {
+        let params = json!({ "items": [{ "id": 1 }] });
+        let req = parse_params::<ItemsReq>(Some(&params)).unwrap();
+        assert_eq!(req.items[0].id, 1);
+    }
 }
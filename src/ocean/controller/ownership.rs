@@ -0,0 +1,147 @@
+use crate::controller::*;
+use crate::types::{Id, UserCode};
+use chrono::prelude::*;
+use chrono::{NaiveDateTime, TimeDelta};
+use diesel::prelude::*;
+
+// Content that can be changed only by its author or admin
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Content {
+    Comment,
+    ForumPost,
+    ForumTopic,
+    Mandela,
+}
+
+// Checks that user may change record from `id` parameter
+pub fn check(data: &mut RequestData, content: Content) -> Result<(), Error> {
+    let req: RequestId = data.params()?;
+    let (author_id, create_ts) = author(&mut data.db.conn, content, req.id)?;
+    let now = Utc::now().naive_utc();
+    let edit_window = config::CONFIG.ownership.edit_window;
+
+    if allowed(&data.user, author_id, create_ts, now, edit_window) {
+        Ok(())
+    } else {
+        Err(api::make_error(api::error::ACCESS_DENIED).into())
+    }
+}
+
+// Admin may change anything, author may change own content within edit window
+pub fn allowed(
+    user: &types::User,
+    author_id: Id,
+    create_ts: NaiveDateTime,
+    now: NaiveDateTime,
+    edit_window: Option<u64>,
+) -> bool {
+    if user.code == UserCode::Admin {
+        return true;
+    }
+
+    if user.code == UserCode::Anonym || user.id != author_id {
+        return false;
+    }
+
+    match edit_window {
+        Some(seconds) => now - create_ts <= TimeDelta::seconds(seconds as i64),
+        None => true,
+    }
+}
+
+fn author(
+    conn: &mut PgConnection,
+    content: Content,
+    record_id: Id,
+) -> QueryResult<(Id, NaiveDateTime)> {
+    use diesel::sql_types::{Int4, Timestamptz};
+
+    #[derive(QueryableByName)]
+    struct Author {
+        #[diesel(sql_type = Int4)]
+        user_id: Id,
+        #[diesel(sql_type = Timestamptz)]
+        create_ts: NaiveDateTime,
+    }
+
+    let author = diesel::sql_query(author_sql(content))
+        .bind::<Int4, _>(record_id)
+        .get_result::<Author>(conn)?;
+
+    Ok((author.user_id, author.create_ts))
+}
+
+// Every content table has author and creation time
+fn author_sql(content: Content) -> String {
+    format!(
+        "SELECT user_id, create_ts FROM {} WHERE id = $1",
+        table(content)
+    )
+}
+
+pub(crate) fn table(content: Content) -> &'static str {
+    match content {
+        Content::Comment => "comments",
+        Content::ForumPost => "forum_posts",
+        Content::ForumTopic => "forum_topics",
+        Content::Mandela => "mandels",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUTHOR_ID: Id = 1;
+    const WINDOW: Option<u64> = Some(60 * 60);
+
+    fn user(id: Id, code: UserCode) -> types::User {
+        types::User {
+            id,
+            code,
+            name: String::new(),
+            blocked: false,
+        }
+    }
+
+    fn create_ts() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn author_within_window() {
+        let now = create_ts() + TimeDelta::minutes(30);
+        let author = user(AUTHOR_ID, UserCode::User);
+        assert!(allowed(&author, AUTHOR_ID, create_ts(), now, WINDOW));
+    }
+
+    #[test]
+    fn author_after_window() {
+        let now = create_ts() + TimeDelta::hours(2);
+        let author = user(AUTHOR_ID, UserCode::User);
+        assert!(!allowed(&author, AUTHOR_ID, create_ts(), now, WINDOW));
+        assert!(allowed(&author, AUTHOR_ID, create_ts(), now, None));
+    }
+
+    #[test]
+    fn other_user() {
+        let other = user(AUTHOR_ID + 1, UserCode::User);
+        assert!(!allowed(&other, AUTHOR_ID, create_ts(), create_ts(), None));
+    }
+
+    #[test]
+    fn anonym() {
+        let anonym = user(AUTHOR_ID, UserCode::Anonym);
+        assert!(!allowed(&anonym, AUTHOR_ID, create_ts(), create_ts(), None));
+    }
+
+    #[test]
+    fn admin() {
+        let now = create_ts() + TimeDelta::days(30);
+        let admin = user(AUTHOR_ID + 1, UserCode::Admin);
+        assert!(allowed(&admin, AUTHOR_ID, create_ts(), now, WINDOW));
+    }
+}
@@ -1,42 +1,43 @@
 use super::*;
 use crate::types::Id;
+use diesel::prelude::*;
+use diesel::sql_types::{Int2, Int4, Int8, Text};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // rating.getMandels
-pub fn get_mandels(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        vote: i16,
-        limit: i32,
-        offset: i32,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetMandelsReq {
+    vote: i16,
+    limit: i32,
+    offset: i32,
+}
 
-    let req: Req = data.params()?;
-
-    use diesel::prelude::*;
-    use diesel::sql_types::Int2;
-    use diesel::sql_types::Int4;
-    use diesel::sql_types::Int8;
-    use diesel::sql_types::Text;
-
-    #[derive(QueryableByName, Serialize)]
-    struct Mandela {
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int4)]
-        title_mode: i32,
-        #[diesel(sql_type = Text)]
-        title: String,
-        #[diesel(sql_type = Text)]
-        what: String,
-        #[diesel(sql_type = Text)]
-        before: String,
-        #[diesel(sql_type = Text)]
-        after: String,
-        #[diesel(sql_type = Int8)]
-        count: i64,
-    }
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Mandela {
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int4)]
+    title_mode: i32,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Text)]
+    what: String,
+    #[diesel(sql_type = Text)]
+    before: String,
+    #[diesel(sql_type = Text)]
+    after: String,
+    #[diesel(sql_type = Int8)]
+    count: i64,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub struct GetMandelsResp {
+    total_count: i64,
+    mandels: Vec<Mandela>,
+}
 
+pub fn get_mandels(mut data: RequestData, req: GetMandelsReq) -> RequestResult<GetMandelsResp> {
     #[derive(QueryableByName)]
     struct TotalCount {
         #[diesel(sql_type = Int8)]
@@ -67,46 +68,38 @@ pub fn get_mandels(mut data: RequestData) -> RequestResult {
     .bind::<Int2, _>(req.vote)
     .load::<TotalCount>(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        total_count: i64,
-        mandels: Vec<Mandela>,
-    }
-
-    let resp = Resp {
+    let resp = GetMandelsResp {
         total_count: total_count[0].count,
         mandels: list,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // rating.getUsers
-pub fn get_users(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        limit: i32,
-        offset: i32,
-    }
-
-    let req: Req = data.params()?;
+#[derive(Deserialize, JsonSchema)]
+pub struct GetUsersReq {
+    limit: i32,
+    offset: i32,
+}
 
-    use diesel::prelude::*;
-    use diesel::sql_types::Int4;
-    use diesel::sql_types::Int8;
-    use diesel::sql_types::Text;
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct User {
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int8)]
+    count: i64,
+}
 
-    #[derive(QueryableByName, Serialize)]
-    struct User {
-        #[diesel(sql_type = Text)]
-        name: String,
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int8)]
-        count: i64,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetUsersResp {
+    user_count: i64,
+    users: Vec<User>,
+}
 
+pub fn get_users(mut data: RequestData, req: GetUsersReq) -> RequestResult<GetUsersResp> {
     let list = diesel::dsl::sql_query(
         "SELECT u.name, u.id, count(m.*)
         FROM users AS u
@@ -134,17 +127,10 @@ pub fn get_users(mut data: RequestData) -> RequestResult {
     )
     .load::<UserCount>(&mut data.db.conn)?;
 
-    #[derive(Serialize)]
-    struct Resp {
-        user_count: i64,
-        users: Vec<User>,
-    }
-
-    let resp = Resp {
+    let resp = GetUsersResp {
         user_count: user_count[0].count,
         users: list,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
@@ -3,49 +3,47 @@ use crate::types::Id;
 use diesel::dsl::*;
 use diesel::prelude::*;
 use diesel::sql_types::{Int4, Int8, Text};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 // search.getAll
-pub fn get_all(mut data: RequestData) -> RequestResult {
-    #[derive(Deserialize)]
-    struct Req {
-        text: String,
-        #[serde(rename(deserialize = "type"))]
-        type_: i8,
-        offset: i64,
-        limit: i64,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct GetAllReq {
+    text: String,
+    #[serde(rename(deserialize = "type"))]
+    type_: i8,
+    offset: i64,
+    limit: i64,
+}
 
-    let req: Req = data.params()?;
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct Record {
+    #[diesel(sql_type = Int4)]
+    title_id: Id,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Int8, column_name = "row")]
+    #[serde(rename = "row")]
+    row_number: i64,
+    #[diesel(sql_type = Text)]
+    content: String,
+}
 
-    #[derive(Serialize)]
-    struct Resp {
-        records: Vec<Record>,
-        total_count: i64,
-    }
+#[derive(Serialize, JsonSchema)]
+pub struct GetAllResp {
+    records: Vec<Record>,
+    total_count: i64,
+}
 
+pub fn get_all(mut data: RequestData, req: GetAllReq) -> RequestResult<GetAllResp> {
     if req.text.is_empty() {
-        let resp = Resp {
+        let resp = GetAllResp {
             records: Vec::new(),
             total_count: 0,
         };
-        let result = serde_json::to_value(&resp)?;
-        return Ok(Some(result));
-    }
-
-    #[derive(QueryableByName, Serialize)]
-    struct Record {
-        #[diesel(sql_type = Int4)]
-        title_id: Id,
-        #[diesel(sql_type = Text)]
-        title: String,
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Int8, column_name = "row")]
-        #[serde(rename = "row")]
-        row_number: i64,
-        #[diesel(sql_type = Text)]
-        content: String,
+        return Ok(resp);
     }
 
     const MANDELA_TYPE: i8 = 0;
@@ -128,10 +126,9 @@ pub fn get_all(mut data: RequestData) -> RequestResult {
         .bind::<Text, _>(&req.text)
         .load::<TotalCount>(&mut data.db.conn)?;
 
-    let resp = Resp {
+    let resp = GetAllResp {
         records,
         total_count: total_count[0].count,
     };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
@@ -1,11 +1,13 @@
 use super::*;
 use crate::api;
 use crate::api::user_cache;
+use crate::session;
 use crate::types::Id;
-use chrono::NaiveDateTime;
 use chrono::prelude::*;
+use chrono::{NaiveDateTime, TimeDelta};
 use diesel::prelude::*;
 use diesel::sql_types::{Bool, Int2, Int4, Int8, Text, Timestamptz};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 #[derive(Queryable, Serialize)]
@@ -16,15 +18,14 @@ struct UserGroup {
 }
 
 // user.getNextId
-pub fn get_next_id(mut data: RequestData) -> RequestResult {
+pub fn get_next_id(mut data: RequestData, _: ()) -> RequestResult<ResponseId> {
     let resp = ResponseId {
         id: next_id(&mut data.db)?,
     };
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
-fn next_id(db: &mut db::Db) -> Result<Id, Box<dyn std::error::Error>> {
+fn next_id(db: &mut db::Db) -> Result<Id, Error> {
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
     let user_id: Id = users
@@ -36,23 +37,24 @@ fn next_id(db: &mut db::Db) -> Result<Id, Box<dyn std::error::Error>> {
 }
 
 // user.create
-pub fn create(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct CreateReq {
+    id: Id,
+    name: String,
+    code: String,
+    token: String,
+    device: Option<String>,
+}
+
+pub fn create(mut data: RequestData, req: CreateReq) -> RequestResult {
     use crate::model::schema::user_groups::dsl::*;
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        name: String,
-        code: String,
-        token: String,
-    }
 
-    let req: Req = data.params()?;
     let next_id = next_id(&mut data.db)?;
 
     if req.id != next_id {
-        return Err(api::make_error(api::error::NEXT_ID_EXPIRED));
+        return Err(api::make_error(api::error::NEXT_ID_EXPIRED).into());
     }
 
     let groups = user_groups
@@ -64,7 +66,7 @@ pub fn create(mut data: RequestData) -> RequestResult {
     pub struct NewUser {
         name: String,
         group_id: Id,
-        token: String,
+        token_hash: String,
         blocked: bool,
     }
 
@@ -73,125 +75,222 @@ pub fn create(mut data: RequestData) -> RequestResult {
     let new_user = NewUser {
         name: req.name,
         group_id: groups.id,
-        token: req.token,
+        token_hash: session::hash_token(&req.token),
         blocked: false,
     };
 
-    diesel::insert_into(users)
-        .values(&new_user)
-        .returning(users::id)
-        .get_result::<Id>(&mut data.db.conn)?;
+    // User without session can't authorize, so both are created or neither
+    data.db.conn.transaction(|conn| {
+        let user_id = diesel::insert_into(users)
+            .values(&new_user)
+            .returning(users::id)
+            .get_result::<Id>(conn)?;
 
-    let user = types::User {
-        id: req.id,
-        code: user_cache::user_code(&req.code),
-        name: user_name,
-        blocked: new_user.blocked,
-    };
+        let user = types::User {
+            id: user_id,
+            code: user_cache::user_code(&req.code),
+            name: user_name,
+            blocked: new_user.blocked,
+        };
 
-    user_cache::set(&new_user.token, user);
+        session::create(conn, user, &req.token, req.device, None)?;
 
-    Ok(None)
+        Ok(())
+    })
 }
 
 // user.auth
-pub fn auth(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::user_groups;
-    use crate::model::schema::user_groups::dsl::*;
-    use crate::model::schema::users;
-    use crate::model::schema::users::dsl::*;
-
-    #[derive(Deserialize)]
-    struct Req {
-        token: String,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct AuthReq {
+    token: String,
+    device: Option<String>, // New session with its own token is created when set
+}
 
-    let req: Req = data.params()?;
-    let user_id;
+#[derive(Serialize, JsonSchema)]
+pub struct AuthResp {
+    code: String,
+    name: String,
+    gender: i16,
+    token: String, // Token of session to use in next requests
+}
 
-    if let Some(u) = user_cache::get(&req.token) {
-        user_id = u.id;
-    } else {
-        return Err(api::make_error(api::error::WRONG_USER_PASSWORD));
-    }
+pub fn auth(mut data: RequestData, req: AuthReq) -> RequestResult<AuthResp> {
+    use crate::model::schema::user_groups;
+    use crate::model::schema::users;
 
-    #[derive(Queryable, Serialize)]
-    pub struct User {
+    #[derive(Queryable)]
+    struct User {
         id: Id,
         name: String,
-        token: String,
-        group_id: Id,
-        create_ts: NaiveDateTime,
-        update_ts: NaiveDateTime,
+        code: String,
         gender: i16,
         blocked: bool,
     }
 
-    let user = users
-        .filter(users::id.eq(user_id))
-        .first::<User>(&mut data.db.conn)?;
-
-    let user_group = user_groups
-        .filter(user_groups::id.eq(user.group_id))
-        .first::<UserGroup>(&mut data.db.conn)?;
-
-    #[derive(Serialize)]
-    struct Resp {
-        code: String,
-        name: String,
-        gender: i16,
-    }
+    let select_user = users::table.inner_join(user_groups::table).select((
+        users::id,
+        users::name,
+        user_groups::code,
+        users::gender,
+        users::blocked,
+    ));
+
+    // Token is either the user's own token or a token of existing session
+    let account = select_user
+        .filter(users::token_hash.eq(session::hash_token(&req.token)))
+        .first::<User>(&mut data.db.conn)
+        .optional()?;
+
+    let (user, session_token) = if let Some(user) = account {
+        let cache_user = types::User {
+            id: user.id,
+            code: user_cache::user_code(&user.code),
+            name: user.name.clone(),
+            blocked: user.blocked,
+        };
+
+        let session_token = if req.device.is_some() {
+            let token = session::new_token();
+            let ttl = TimeDelta::seconds(config::CONFIG.sessions.ttl as i64);
+            let expire_ts = Utc::now().naive_utc() + ttl;
+            session::create(
+                &mut data.db.conn,
+                cache_user,
+                &token,
+                req.device,
+                Some(expire_ts),
+            )?;
+            token
+        } else {
+            // Primary session is restored after logout
+            if user_cache::find(&mut data.db.conn, &req.token)?.is_none() {
+                session::create(&mut data.db.conn, cache_user, &req.token, None, None)?;
+            }
+            req.token
+        };
+
+        (user, session_token)
+    } else if let Some(s) = user_cache::find(&mut data.db.conn, &req.token)? {
+        let user = select_user
+            .filter(users::id.eq(s.user.id))
+            .first::<User>(&mut data.db.conn)?;
+        (user, req.token)
+    } else {
+        return Err(api::make_error(api::error::WRONG_USER_PASSWORD).into());
+    };
 
-    let resp = Resp {
-        code: user_group.code,
+    let resp = AuthResp {
+        code: user.code,
         name: user.name,
         gender: user.gender,
+        token: session_token,
     };
 
-    let result = serde_json::to_value(&resp)?;
-    Ok(Some(result))
+    Ok(resp)
 }
 
 // user.logout
-pub fn logout(_data: RequestData) -> RequestResult {
-    Ok(None)
+pub fn logout(mut data: RequestData, _: ()) -> RequestResult {
+    session::revoke(&mut data.db.conn, data.user.id, data.session_id)?;
+    Ok(())
 }
 
-// user.getOne
-pub fn get_one(mut data: RequestData) -> RequestResult {
-    let req: RequestId = data.params()?;
+// user.getSessions
+#[derive(Serialize, JsonSchema)]
+pub struct Session {
+    id: Id,
+    device: Option<String>,
+    create_ts: NaiveDateTime,
+    last_seen_ts: NaiveDateTime,
+    expire_ts: Option<NaiveDateTime>,
+    current: bool, // Session of this request
+}
 
-    #[derive(QueryableByName, Serialize)]
-    struct User {
-        #[diesel(sql_type = Int4)]
-        id: Id,
-        #[diesel(sql_type = Text)]
-        name: String,
-        #[diesel(sql_type = Text)]
-        code: String,
-        #[diesel(sql_type = Int2)]
-        gender: i16,
-        #[diesel(sql_type = Bool)]
-        blocked: bool,
-        #[diesel(sql_type = Timestamptz)]
-        create_ts: NaiveDateTime,
-        #[diesel(sql_type = Int8)]
-        mandela_count: i64,
-        #[diesel(sql_type = Int8)]
-        comment_count: i64,
-        #[diesel(sql_type = Int8)]
-        forum_topic_count: i64,
-        #[diesel(sql_type = Int8)]
-        forum_post_count: i64,
-        #[diesel(sql_type = Int8)]
-        like_count: i64,
-        #[diesel(sql_type = Int8)]
-        dislike_count: i64,
+pub fn get_sessions(mut data: RequestData, _: ()) -> RequestResult<Vec<Session>> {
+    use crate::model::schema::sessions;
+
+    let now = Utc::now().naive_utc();
+
+    let list = sessions::table
+        .select((
+            sessions::id,
+            sessions::device,
+            sessions::create_ts,
+            sessions::last_seen_ts,
+            sessions::expire_ts,
+        ))
+        .filter(sessions::user_id.eq(data.user.id))
+        .filter(
+            sessions::expire_ts
+                .is_null()
+                .or(sessions::expire_ts.gt(now)),
+        )
+        .order(sessions::last_seen_ts.desc())
+        .load::<(
+            Id,
+            Option<String>,
+            NaiveDateTime,
+            NaiveDateTime,
+            Option<NaiveDateTime>,
+        )>(&mut data.db.conn)?;
+
+    let resp: Vec<Session> = list
+        .into_iter()
+        .map(|(id, device, create_ts, last_seen_ts, expire_ts)| Session {
+            id,
+            device,
+            create_ts,
+            last_seen_ts,
+            expire_ts,
+            current: id == data.session_id,
+        })
+        .collect();
+
+    Ok(resp)
+}
+
+// user.revokeSession
+pub fn revoke_session(mut data: RequestData, req: RequestId) -> RequestResult {
+    if session::revoke(&mut data.db.conn, data.user.id, req.id)? {
+        Ok(())
+    } else {
+        Err(api::make_error(api::error::RECORD_NOT_FOUND).into())
     }
+}
 
+// user.getOne
+#[derive(QueryableByName, Serialize, JsonSchema)]
+pub struct GetOneResp {
+    #[diesel(sql_type = Int4)]
+    id: Id,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    code: String,
+    #[diesel(sql_type = Int2)]
+    gender: i16,
+    #[diesel(sql_type = Bool)]
+    blocked: bool,
+    #[diesel(sql_type = Timestamptz)]
+    create_ts: NaiveDateTime,
+    #[diesel(sql_type = Int8)]
+    mandela_count: i64,
+    #[diesel(sql_type = Int8)]
+    comment_count: i64,
+    #[diesel(sql_type = Int8)]
+    forum_topic_count: i64,
+    #[diesel(sql_type = Int8)]
+    forum_post_count: i64,
+    #[diesel(sql_type = Int8)]
+    like_count: i64,
+    #[diesel(sql_type = Int8)]
+    dislike_count: i64,
+}
+
+pub fn get_one(mut data: RequestData, req: RequestId) -> RequestResult<GetOneResp> {
     use diesel::dsl::*;
 
-    let user = sql_query(format!(
+    let user = sql_query(
         "SELECT u.id, u.name, ug.code, u.gender, u.blocked, u.create_ts,
             (SELECT count(*) FROM mandels WHERE user_id = u.id) AS mandela_count,
             (SELECT count(*) FROM comments WHERE user_id = u.id) AS comment_count,
@@ -215,36 +314,32 @@ pub fn get_one(mut data: RequestData) -> RequestResult {
             WHERE fp.user_id = $1 and l.value = 1) AS dislike_count
         FROM users AS u
             JOIN user_groups AS ug ON ug.id = u.group_id
-        WHERE u.id = $1"
-    ))
+        WHERE u.id = $1",
+    )
     .bind::<Int4, _>(req.id)
-    .load::<User>(&mut data.db.conn)?;
+    .load::<GetOneResp>(&mut data.db.conn)?;
 
-    if !user.is_empty() {
-        let result = serde_json::to_value(&user[0])?;
-        Ok(Some(result))
-    } else {
-        Err(api::make_error(api::error::RECORD_NOT_FOUND))
+    match user.into_iter().next() {
+        Some(user) => Ok(user),
+        None => Err(api::make_error(api::error::RECORD_NOT_FOUND).into()),
     }
 }
 
 // user.update
-pub fn update(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateReq {
+    id: Id,
+    name: String,
+    code: String,
+    gender: i16,
+    blocked: bool,
+}
+
+pub fn update(mut data: RequestData, req: UpdateReq) -> RequestResult {
     use crate::model::schema::user_groups::dsl::*;
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        id: Id,
-        name: String,
-        code: String,
-        gender: i16,
-        blocked: bool,
-    }
-
-    let req: Req = data.params()?;
-
     let groups = user_groups
         .filter(code.eq(req.code))
         .first::<UserGroup>(&mut data.db.conn)?;
@@ -271,24 +366,27 @@ pub fn update(mut data: RequestData) -> RequestResult {
         .set(&update_user)
         .execute(&mut data.db.conn)?;
 
-    user_cache::update_blocked(req.id, req.blocked);
+    user_cache::update_user(&types::User {
+        id: req.id,
+        code: user_cache::user_code(&groups.code),
+        name: update_user.name,
+        blocked: update_user.blocked,
+    });
 
-    Ok(None)
+    Ok(())
 }
 
 // user.updateProfile
-pub fn update_profile(mut data: RequestData) -> RequestResult {
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateProfileReq {
+    name: String,
+    gender: i16,
+}
+
+pub fn update_profile(mut data: RequestData, req: UpdateProfileReq) -> RequestResult {
     use crate::model::schema::users;
     use crate::model::schema::users::dsl::*;
 
-    #[derive(Deserialize)]
-    struct Req {
-        name: String,
-        gender: i16,
-    }
-
-    let req: Req = data.params()?;
-
     #[derive(AsChangeset)]
     #[diesel(table_name = users)]
     pub struct UpdateUser {
@@ -307,24 +405,30 @@ pub fn update_profile(mut data: RequestData) -> RequestResult {
         .set(&update_user)
         .execute(&mut data.db.conn)?;
 
-    Ok(None)
+    user_cache::update_user(&types::User {
+        name: update_user.name,
+        ..data.user
+    });
+
+    Ok(())
 }
 
 // user.updateToken
-pub fn update_token(mut data: RequestData) -> RequestResult {
-    use crate::model::schema::users::dsl::*;
-
-    #[derive(Deserialize)]
-    struct Req {
-        token: String,
-    }
+#[derive(Deserialize, JsonSchema)]
+pub struct UpdateTokenReq {
+    token: String,
+}
 
-    let req: Req = data.params()?;
+pub fn update_token(mut data: RequestData, req: UpdateTokenReq) -> RequestResult {
+    use crate::model::schema::users::dsl::*;
 
     diesel::update(users.filter(id.eq(data.user.id)))
-        .set(token.eq(&req.token))
+        .set(token_hash.eq(session::hash_token(&req.token)))
         .execute(&mut data.db.conn)?;
 
+    // Sessions opened with the old token are closed on all devices
+    session::revoke_all(&mut data.db.conn, data.user.id)?;
+
     let user = types::User {
         id: data.user.id,
         code: data.user.code,
@@ -332,16 +436,18 @@ pub fn update_token(mut data: RequestData) -> RequestResult {
         blocked: data.user.blocked,
     };
 
-    user_cache::set(&req.token, user);
+    session::create(&mut data.db.conn, user, &req.token, None, None)?;
 
-    Ok(None)
+    Ok(())
 }
 
 // user.delete
-pub fn delete(mut data: RequestData) -> RequestResult {
+pub fn delete(mut data: RequestData, req: RequestId) -> RequestResult {
     use crate::model::schema::users::dsl::*;
-    let req: RequestId = data.params()?;
 
+    // Sessions are deleted by cascade
     diesel::delete(users.filter(id.eq(req.id))).execute(&mut data.db.conn)?;
-    Ok(None)
+    user_cache::remove_user(req.id);
+
+    Ok(())
 }
@@ -1,27 +1,159 @@
 use crate::config;
+use crate::metrics;
 use diesel::pg::PgConnection;
 use diesel::prelude::*;
+use diesel::r2d2::{ConnectionManager, Pool, PoolError, PooledConnection};
+use diesel_migrations::{EmbeddedMigrations, embed_migrations};
+use std::fmt;
+use std::sync::OnceLock;
+use std::time;
+
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!();
+
+pub type PgPool = Pool<ConnectionManager<PgConnection>>;
+
+static POOL: OnceLock<PgPool> = OnceLock::new();
 
 pub struct Db {
-    pub conn: PgConnection,
+    pub conn: PooledConnection<ConnectionManager<PgConnection>>,
+    statement_timeout: bool,
 }
 
 impl Db {
-    pub fn new() -> Db {
-        let database_url = format!(
-            "postgres://{}:{}@localhost/{}",
-            config::CONFIG.postgres.username,
-            config::CONFIG.postgres.password,
-            config::CONFIG.postgres.database
-        );
-        let conn = PgConnection::establish(&database_url)
-            .unwrap_or_else(|_| panic!("Error connecting to {}", database_url));
-        Db { conn }
+    // Checks out connection from the pool. It is returned back on drop.
+    pub fn new() -> Result<Db, PoolError> {
+        let start = time::Instant::now();
+        let conn = pool().get();
+        metrics::observe_db_connection(start.elapsed());
+
+        Ok(Db {
+            conn: conn?,
+            statement_timeout: false,
+        })
+    }
+
+    // Statements running longer than timeout are cancelled by server, so a handler
+    // whose client already got timeout error releases the connection soon
+    pub fn with_timeout(timeout: time::Duration) -> Result<Db, Error> {
+        let mut db = Db::new().map_err(Error::Pool)?;
+        let millis = timeout.as_millis().max(1);
+
+        diesel::sql_query(format!("SET statement_timeout = {}", millis))
+            .execute(&mut db.conn)
+            .map_err(Error::Query)?;
+
+        db.statement_timeout = true;
+        Ok(db)
+    }
+}
+
+// Connection goes back to the pool with default timeout for other users
+impl Drop for Db {
+    fn drop(&mut self) {
+        if self.statement_timeout {
+            let _ = diesel::sql_query("SET statement_timeout = DEFAULT").execute(&mut self.conn);
+        }
     }
 }
 
-impl Default for Db {
-    fn default() -> Self {
-        Self::new()
+// Messages never contain password, so they are safe to log
+#[derive(Debug)]
+pub enum Error {
+    Connection(String, ConnectionError),
+    Pool(PoolError),
+    Query(diesel::result::Error),
+}
+
+impl std::error::Error for Error {}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Connection(target, e) => {
+                write!(f, "Database connection error: {}: {}", target, e)
+            }
+            Error::Pool(e) => write!(f, "Database pool error: {}", e),
+            Error::Query(e) => write!(f, "Database query error: {}", e),
+        }
     }
 }
+
+pub fn init() -> Result<(), Error> {
+    let postgres = &config::CONFIG.postgres;
+    let database_url = connection_string(postgres);
+
+    // Establish a single connection first to get the real reason of failure,
+    // pool only reports a checkout timeout
+    PgConnection::establish(&database_url)
+        .map_err(|e| Error::Connection(connection_target(postgres), e))?;
+
+    let manager = ConnectionManager::<PgConnection>::new(database_url);
+
+    let pool = Pool::builder()
+        .min_idle(Some(postgres.pool.min_size))
+        .max_size(postgres.pool.max_size)
+        .connection_timeout(time::Duration::from_secs(postgres.pool.timeout))
+        .test_on_check_out(postgres.pool.health_check)
+        .build(manager)
+        .map_err(Error::Pool)?;
+
+    POOL.set(pool).ok();
+    Ok(())
+}
+
+fn pool() -> &'static PgPool {
+    POOL.get().expect("Database pool is not initialized")
+}
+
+// Full URL from config has priority over separate fields. Otherwise libpq
+// keyword/value string is built, so unix socket directory can be used as host.
+fn connection_string(postgres: &config::Postgres) -> String {
+    if let Some(url) = &postgres.url {
+        return url.clone();
+    }
+
+    let host = postgres.socket.as_ref().unwrap_or(&postgres.host);
+
+    let mut params = vec![
+        ("host", host.as_str()),
+        ("user", postgres.username.as_str()),
+        ("password", postgres.password.as_str()),
+        ("dbname", postgres.database.as_str()),
+    ];
+
+    let port = postgres.port.to_string();
+    params.push(("port", &port));
+
+    if let Some(sslmode) = &postgres.sslmode {
+        params.push(("sslmode", sslmode));
+    }
+
+    params
+        .into_iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| format!("{}='{}'", key, escape(value)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn connection_target(postgres: &config::Postgres) -> String {
+    if let Some(u) = &postgres.url {
+        return match url::Url::parse(u) {
+            Ok(mut url) => {
+                url.set_password(None).ok();
+                url.to_string()
+            }
+            Err(_) => "<invalid url>".to_string(),
+        };
+    }
+
+    if let Some(socket) = &postgres.socket {
+        return format!("{}/{}", socket, postgres.database);
+    }
+
+    format!("{}:{}/{}", postgres.host, postgres.port, postgres.database)
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\'', "\\'")
+}
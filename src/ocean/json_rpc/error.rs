@@ -1,7 +1,14 @@
 use crate::api;
 use serde::Serialize;
 
-#[derive(Serialize)]
+// Codes reserved by JSON-RPC 2.0 specification
+pub const PARSE_ERROR: api::error::ErrorCode = -32700;
+pub const INVALID_REQUEST: api::error::ErrorCode = -32600;
+pub const METHOD_NOT_FOUND: api::error::ErrorCode = -32601;
+pub const INVALID_PARAMS: api::error::ErrorCode = -32602;
+pub const INTERNAL_ERROR: api::error::ErrorCode = -32603;
+
+#[derive(Clone, Serialize)]
 pub struct Error {
     pub code: api::error::ErrorCode,
     pub message: String,
@@ -21,4 +28,19 @@ impl Error {
     pub fn from_api_error(err: &api::error::Error) -> Error {
         Self::new(err.code(), err.message(), err.data())
     }
+
+    // Replaces project error codes that have a counterpart in the specification.
+    // Application specific codes are passed as is.
+    pub fn to_standard(&self) -> Error {
+        let code = match self.code {
+            api::error::PARSE_ERROR => PARSE_ERROR,
+            api::error::INVALID_REQUEST => INVALID_REQUEST,
+            api::error::CONTROLLER_NOT_FOUND | api::error::METHOD_NOT_FOUND => METHOD_NOT_FOUND,
+            api::error::PARAMETER_NOT_FOUND | api::error::INVALID_PARAMETER => INVALID_PARAMS,
+            api::error::INTERNAL_SERVER_ERROR => INTERNAL_ERROR,
+            code => code,
+        };
+
+        Self::new(code, self.message.clone(), self.data.clone())
+    }
 }
@@ -3,5 +3,5 @@ pub mod request;
 pub mod response;
 
 pub use error::Error;
-pub use request::Request;
+pub use request::{Id, Request, Version};
 pub use response::Response;
@@ -1,8 +1,87 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+
+pub const VERSION: &str = "2.0";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Version {
+    // Project specific protocol without `jsonrpc` member and with string ids
+    Legacy,
+    // JSON-RPC 2.0 specification
+    V2,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(untagged)]
+pub enum Id {
+    Number(i64),
+    String(String),
+    Null,
+}
+
+impl Id {
+    pub fn to_legacy(&self) -> String {
+        match self {
+            Id::Number(n) => n.to_string(),
+            Id::String(s) => s.clone(),
+            Id::Null => "".to_string(),
+        }
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct Request {
-    pub id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jsonrpc: Option<String>,
+    // Absent id means notification, `null` id is kept as `Id::Null`
+    #[serde(
+        default,
+        deserialize_with = "deserialize_id",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Id>,
     pub method: String,
     pub params: Option<serde_json::Value>,
 }
+
+impl Request {
+    pub fn version(&self) -> Version {
+        if self.jsonrpc.is_some() {
+            Version::V2
+        } else {
+            Version::Legacy
+        }
+    }
+}
+
+pub fn version_of(value: &serde_json::Value) -> Version {
+    if value.get("jsonrpc").is_some() {
+        Version::V2
+    } else {
+        Version::Legacy
+    }
+}
+
+// Batch is answered according to specification if any of its calls is
+pub fn batch_version(list: &[serde_json::Value]) -> Version {
+    if list.iter().any(|v| version_of(v) == Version::V2) {
+        Version::V2
+    } else {
+        Version::Legacy
+    }
+}
+
+// Body that is not valid JSON is only checked for the member name
+pub fn raw_version_of(body: &[u8]) -> Version {
+    if body.windows(9).any(|w| w == b"\"jsonrpc\"") {
+        Version::V2
+    } else {
+        Version::Legacy
+    }
+}
+
+fn deserialize_id<'de, D>(deserializer: D) -> Result<Option<Id>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Id::deserialize(deserializer).map(Some)
+}
@@ -1,23 +1,154 @@
-use crate::json_rpc::Error;
-use serde::Serialize;
+use crate::json_rpc::request::VERSION;
+use crate::json_rpc::{Error, Id, Version};
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
 
-#[derive(Serialize)]
 pub struct Response {
-    pub id: String,
+    pub version: Version,
+    pub id: Option<Id>,
     pub method: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<Error>,
 }
 
 impl Default for Response {
     fn default() -> Self {
         Response {
-            id: "".to_string(),
+            version: Version::Legacy,
+            id: None,
             method: "".to_string(),
             result: None,
             error: None,
         }
     }
 }
+
+impl Serialize for Response {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(None)?;
+
+        match self.version {
+            Version::Legacy => {
+                let id = self.id.as_ref().map(Id::to_legacy).unwrap_or_default();
+                map.serialize_entry("id", &id)?;
+                map.serialize_entry("method", &self.method)?;
+
+                if let Some(result) = &self.result {
+                    map.serialize_entry("result", result)?;
+                }
+
+                if let Some(error) = &self.error {
+                    map.serialize_entry("error", error)?;
+                }
+            }
+            Version::V2 => {
+                map.serialize_entry("jsonrpc", VERSION)?;
+
+                // Exactly one of `result` or `error` must be present
+                if let Some(error) = &self.error {
+                    map.serialize_entry("error", &error.to_standard())?;
+                } else {
+                    map.serialize_entry("result", &self.result)?;
+                }
+
+                map.serialize_entry("id", &self.id.clone().unwrap_or(Id::Null))?;
+            }
+        }
+
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api;
+    use serde_json::json;
+
+    fn error(code: api::error::ErrorCode) -> Option<Error> {
+        Some(Error::from_api_error(&api::Error::new(code, None)))
+    }
+
+    #[test]
+    fn legacy_form() {
+        let result = Response {
+            id: Some(Id::Number(7)),
+            method: "ping".to_string(),
+            result: Some(json!({ "id": 1 })),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            json!({ "id": "7", "method": "ping", "result": { "id": 1 } })
+        );
+
+        // Errors without request keep project codes and empty id and method
+        let parse_error = Response {
+            error: error(api::error::PARSE_ERROR),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&parse_error).unwrap(),
+            json!({ "id": "", "method": "", "error": { "code": 1, "message": "Parse error" } })
+        );
+    }
+
+    #[test]
+    fn specification_form() {
+        let result = Response {
+            version: Version::V2,
+            id: Some(Id::String("a".to_string())),
+            method: "ping".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&result).unwrap(),
+            json!({ "jsonrpc": "2.0", "result": null, "id": "a" })
+        );
+
+        let parse_error = Response {
+            version: Version::V2,
+            error: error(api::error::PARSE_ERROR),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&parse_error).unwrap(),
+            json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32700, "message": "Parse error" },
+                "id": null
+            })
+        );
+    }
+
+    #[test]
+    fn batch_keeps_version_of_each_call() {
+        let batch = vec![
+            Response {
+                id: Some(Id::Number(1)),
+                error: error(api::error::ACCESS_DENIED),
+                ..Default::default()
+            },
+            Response {
+                version: Version::V2,
+                id: Some(Id::Number(2)),
+                error: error(api::error::METHOD_NOT_FOUND),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(
+            serde_json::to_value(&batch).unwrap(),
+            json!([
+                { "id": "1", "method": "", "error": { "code": 103, "message": "Access denied" } },
+                {
+                    "jsonrpc": "2.0",
+                    "error": { "code": -32601, "message": "Method not found" },
+                    "id": 2
+                }
+            ])
+        );
+    }
+}
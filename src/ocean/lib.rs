@@ -4,10 +4,15 @@ pub mod config;
 pub mod controller;
 pub mod db;
 pub mod json_rpc;
+pub mod logger;
+pub mod metrics;
 pub mod model;
+pub mod session;
+pub mod shutdown;
 pub mod telegram_bot;
 pub mod trash_monitor;
 pub mod watchdog;
+pub mod worker;
 
 pub mod types {
     pub type Id = i32;
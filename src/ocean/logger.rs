@@ -0,0 +1,50 @@
+use crate::config::LogFormat;
+use log::kv::{self, Key, Value, VisitSource};
+use std::io::Write;
+
+pub fn init(format: &LogFormat) {
+    let mut builder = env_logger::builder();
+
+    match format {
+        LogFormat::Text => {
+            builder.format_timestamp(None);
+        }
+        LogFormat::Json => {
+            builder.format(|buf, record| {
+                let mut line = serde_json::Map::new();
+                line.insert("ts".to_string(), chrono::Utc::now().to_rfc3339().into());
+                line.insert("level".to_string(), record.level().as_str().into());
+                line.insert("target".to_string(), record.target().into());
+                line.insert("message".to_string(), record.args().to_string().into());
+
+                record.key_values().visit(&mut JsonFields(&mut line)).ok();
+
+                writeln!(buf, "{}", serde_json::Value::Object(line))
+            });
+        }
+    }
+
+    builder.init();
+}
+
+// Key-value pairs of record become top level fields of JSON line
+struct JsonFields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(b) = value.to_bool() {
+            b.into()
+        } else if let Some(i) = value.to_i64() {
+            i.into()
+        } else if let Some(u) = value.to_u64() {
+            u.into()
+        } else if let Some(f) = value.to_f64() {
+            f.into()
+        } else {
+            value.to_string().into()
+        };
+
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+use crate::api::error::ErrorCode;
+use crate::api::user_cache;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time;
+
+// Upper bounds of histogram buckets in seconds
+const BUCKETS: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()], // cumulative
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: time::Duration) {
+        let value = duration.as_secs_f64();
+
+        for (bucket, bound) in self.buckets.iter_mut().zip(BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+
+        for (bucket, bound) in self.buckets.iter().zip(BUCKETS) {
+            writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, separator, bound, bucket
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, separator, self.count
+        )
+        .unwrap();
+        writeln!(out, "{}_sum{{{}}} {}", name, labels, self.sum).unwrap();
+        writeln!(out, "{}_count{{{}}} {}", name, labels, self.count).unwrap();
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    rpc_requests: Mutex<BTreeMap<(String, ErrorCode), Histogram>>,
+    db_connection: Mutex<Histogram>,
+    active_connections: AtomicI64,
+    tls_handshake_failures: AtomicU64,
+    user_cache_hits: AtomicU64,
+    user_cache_misses: AtomicU64,
+    trash_monitor_runs: AtomicU64,
+    trash_monitor_moved: AtomicU64,
+    trash_monitor_restored: AtomicU64,
+    telegram_success: AtomicU64,
+    telegram_failure: AtomicU64,
+}
+
+static REGISTRY: LazyLock<Registry> = LazyLock::new(Registry::default);
+
+// Code is 0 for successful calls
+pub fn observe_rpc(method: &str, code: ErrorCode, duration: time::Duration) {
+    REGISTRY
+        .rpc_requests
+        .lock()
+        .unwrap()
+        .entry((method.to_string(), code))
+        .or_default()
+        .observe(duration);
+}
+
+pub fn observe_db_connection(duration: time::Duration) {
+    REGISTRY.db_connection.lock().unwrap().observe(duration);
+}
+
+pub fn tls_handshake_failed() {
+    REGISTRY
+        .tls_handshake_failures
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn user_cache_hit() {
+    REGISTRY.user_cache_hits.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn user_cache_miss() {
+    REGISTRY.user_cache_misses.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn trash_monitor_run(moved: usize, restored: usize) {
+    REGISTRY.trash_monitor_runs.fetch_add(1, Ordering::Relaxed);
+    REGISTRY
+        .trash_monitor_moved
+        .fetch_add(moved as u64, Ordering::Relaxed);
+    REGISTRY
+        .trash_monitor_restored
+        .fetch_add(restored as u64, Ordering::Relaxed);
+}
+
+pub fn telegram_sent(success: bool) {
+    let counter = if success {
+        &REGISTRY.telegram_success
+    } else {
+        &REGISTRY.telegram_failure
+    };
+
+    counter.fetch_add(1, Ordering::Relaxed);
+}
+
+// Counts connection as active while alive
+pub struct ConnectionGuard;
+
+impl ConnectionGuard {
+    pub fn new() -> Self {
+        REGISTRY.active_connections.fetch_add(1, Ordering::Relaxed);
+        Self
+    }
+}
+
+impl Default for ConnectionGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        REGISTRY.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Prometheus text exposition format
+pub fn render() -> String {
+    let mut out = String::new();
+
+    header(
+        &mut out,
+        "ocean_rpc_requests_total",
+        "counter",
+        "JSON-RPC calls by method and error code",
+    );
+
+    let rpc_requests = REGISTRY.rpc_requests.lock().unwrap();
+
+    for ((method, code), histogram) in rpc_requests.iter() {
+        writeln!(
+            out,
+            "ocean_rpc_requests_total{{{}}} {}",
+            rpc_labels(method, *code),
+            histogram.count
+        )
+        .unwrap();
+    }
+
+    header(
+        &mut out,
+        "ocean_rpc_request_duration_seconds",
+        "histogram",
+        "JSON-RPC call latency by method and error code",
+    );
+
+    for ((method, code), histogram) in rpc_requests.iter() {
+        histogram.render(
+            &mut out,
+            "ocean_rpc_request_duration_seconds",
+            &rpc_labels(method, *code),
+        );
+    }
+
+    drop(rpc_requests);
+
+    header(
+        &mut out,
+        "ocean_db_connection_seconds",
+        "histogram",
+        "Time to get database connection from the pool",
+    );
+    REGISTRY
+        .db_connection
+        .lock()
+        .unwrap()
+        .render(&mut out, "ocean_db_connection_seconds", "");
+
+    let values = [
+        (
+            "ocean_active_connections",
+            "gauge",
+            "Open client connections",
+            REGISTRY.active_connections.load(Ordering::Relaxed),
+        ),
+        (
+            "ocean_tls_handshake_failures_total",
+            "counter",
+            "Failed TLS handshakes",
+            REGISTRY.tls_handshake_failures.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "ocean_user_cache_size",
+            "gauge",
+            "Sessions in cache",
+            user_cache::len() as i64,
+        ),
+        (
+            "ocean_user_cache_hits_total",
+            "counter",
+            "Sessions found in cache",
+            REGISTRY.user_cache_hits.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "ocean_user_cache_misses_total",
+            "counter",
+            "Sessions absent or stale in cache",
+            REGISTRY.user_cache_misses.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "ocean_trash_monitor_runs_total",
+            "counter",
+            "Trash monitor runs",
+            REGISTRY.trash_monitor_runs.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "ocean_trash_monitor_moved_total",
+            "counter",
+            "Mandels moved to trash by monitor",
+            REGISTRY.trash_monitor_moved.load(Ordering::Relaxed) as i64,
+        ),
+        (
+            "ocean_trash_monitor_restored_total",
+            "counter",
+            "Mandels restored from trash by monitor",
+            REGISTRY.trash_monitor_restored.load(Ordering::Relaxed) as i64,
+        ),
+    ];
+
+    for (name, kind, help, value) in values {
+        header(&mut out, name, kind, help);
+        writeln!(out, "{} {}", name, value).unwrap();
+    }
+
+    header(
+        &mut out,
+        "ocean_telegram_messages_total",
+        "counter",
+        "Telegram messages by send result",
+    );
+
+    for (result, counter) in [
+        ("success", &REGISTRY.telegram_success),
+        ("failure", &REGISTRY.telegram_failure),
+    ] {
+        writeln!(
+            out,
+            "ocean_telegram_messages_total{{result=\"{}\"}} {}",
+            result,
+            counter.load(Ordering::Relaxed)
+        )
+        .unwrap();
+    }
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn rpc_labels(method: &str, code: ErrorCode) -> String {
+    format!("method=\"{}\",code=\"{}\"", escape(method), code)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -122,6 +122,19 @@ table! {
     }
 }
 
+table! {
+    sessions (id) {
+        id -> Int4,
+        user_id -> Int4,
+        token_hash -> Text,
+        device -> Nullable<Text>,
+        create_ts -> Timestamptz,
+        last_seen_ts -> Timestamptz,
+        expire_ts -> Nullable<Timestamptz>,
+        unkeyed_hash -> Nullable<Text>,
+    }
+}
+
 table! {
     user_groups (id) {
         id -> Int4,
@@ -134,12 +147,13 @@ table! {
     users (id) {
         id -> Int4,
         name -> Text,
-        token -> Text,
         group_id -> Int4,
         create_ts -> Timestamptz,
         update_ts -> Timestamptz,
         gender -> Int2,
         blocked -> Bool,
+        token_hash -> Nullable<Text>,
+        unkeyed_hash -> Nullable<Text>,
     }
 }
 
@@ -178,6 +192,7 @@ joinable!(likes -> users (user_id));
 joinable!(mandels -> users (user_id));
 joinable!(marks -> mandels (mandela_id));
 joinable!(marks -> users (user_id));
+joinable!(sessions -> users (user_id));
 joinable!(users -> user_groups (group_id));
 joinable!(votes -> mandels (mandela_id));
 joinable!(votes -> users (user_id));
@@ -194,6 +209,7 @@ allow_tables_to_appear_in_same_query!(
     likes,
     mandels,
     marks,
+    sessions,
     user_groups,
     users,
     values,
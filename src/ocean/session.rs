@@ -0,0 +1,211 @@
+use crate::api::user_cache;
+use crate::config;
+use crate::db;
+use crate::types;
+use crate::worker::Worker;
+use chrono::NaiveDateTime;
+use chrono::prelude::*;
+use diesel::prelude::*;
+use hmac::{Hmac, Mac};
+use log::{error, info};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::{LazyLock, Mutex};
+use std::time;
+
+const TOKEN_SIZE: usize = 32; // bytes
+
+// Last seen time by session id since previous write to database
+static SEEN: LazyLock<Mutex<HashMap<types::Id, NaiveDateTime>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+// Only keyed hash of token is stored, so a database dump does not reveal tokens
+pub fn hash_token(token: &str) -> String {
+    key_hash(&hex(&Sha256::digest(token.as_bytes())))
+}
+
+// Token is hashed before keying, so hashes stored by migration without key can be keyed
+fn key_hash(unkeyed_hash: &str) -> String {
+    let secret = config::CONFIG.sessions.token_secret.as_bytes();
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(unkeyed_hash.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+pub fn new_token() -> String {
+    let mut bytes = [0u8; TOKEN_SIZE];
+    rand::rng().fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        write!(out, "{:02x}", b).unwrap();
+        out
+    })
+}
+
+// Session works immediately after creation
+pub fn create(
+    conn: &mut PgConnection,
+    user: types::User,
+    token: &str,
+    device: Option<String>,
+    expire_ts: Option<NaiveDateTime>,
+) -> QueryResult<types::Id> {
+    use crate::model::schema::sessions;
+
+    #[derive(Insertable)]
+    #[diesel(table_name = sessions)]
+    struct NewSession {
+        user_id: types::Id,
+        token_hash: String,
+        device: Option<String>,
+        expire_ts: Option<NaiveDateTime>,
+    }
+
+    let new_session = NewSession {
+        user_id: user.id,
+        token_hash: hash_token(token),
+        device,
+        expire_ts,
+    };
+
+    let id = diesel::insert_into(sessions::table)
+        .values(&new_session)
+        .returning(sessions::id)
+        .get_result::<types::Id>(conn)?;
+
+    let session = user_cache::Session {
+        id,
+        user,
+        expire_ts,
+    };
+
+    user_cache::set(&new_session.token_hash, session);
+
+    Ok(id)
+}
+
+// Returns false when user has no such session
+pub fn revoke(conn: &mut PgConnection, user_id: types::Id, id: types::Id) -> QueryResult<bool> {
+    use crate::model::schema::sessions;
+
+    let hashes = diesel::delete(
+        sessions::table
+            .filter(sessions::id.eq(id))
+            .filter(sessions::user_id.eq(user_id)),
+    )
+    .returning(sessions::token_hash)
+    .get_results::<String>(conn)?;
+
+    hashes.iter().for_each(|h| user_cache::remove(h));
+    Ok(!hashes.is_empty())
+}
+
+pub fn revoke_all(conn: &mut PgConnection, user_id: types::Id) -> QueryResult<()> {
+    use crate::model::schema::sessions;
+
+    let hashes = diesel::delete(sessions::table.filter(sessions::user_id.eq(user_id)))
+        .returning(sessions::token_hash)
+        .get_results::<String>(conn)?;
+
+    hashes.iter().for_each(|h| user_cache::remove(h));
+    Ok(())
+}
+
+// Called on every request, database is updated by worker in batches
+pub fn touch(id: types::Id) {
+    SEEN.lock().unwrap().insert(id, Utc::now().naive_utc());
+}
+
+// Hashes stored by migration without server secret are keyed on startup. Keying is
+// idempotent, so instances starting together only repeat the same updates.
+pub fn key_tokens(conn: &mut PgConnection) -> QueryResult<()> {
+    use crate::model::schema::sessions;
+    use crate::model::schema::users;
+
+    conn.transaction(|conn| {
+        let user_hashes = users::table
+            .filter(users::unkeyed_hash.is_not_null())
+            .select((users::id, users::unkeyed_hash.assume_not_null()))
+            .load::<(types::Id, String)>(conn)?;
+
+        for (id, unkeyed_hash) in &user_hashes {
+            diesel::update(users::table.filter(users::id.eq(id)))
+                .set((
+                    users::token_hash.eq(key_hash(unkeyed_hash)),
+                    users::unkeyed_hash.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
+
+        let session_hashes = sessions::table
+            .filter(sessions::unkeyed_hash.is_not_null())
+            .select((sessions::id, sessions::unkeyed_hash.assume_not_null()))
+            .load::<(types::Id, String)>(conn)?;
+
+        for (id, unkeyed_hash) in &session_hashes {
+            diesel::update(sessions::table.filter(sessions::id.eq(id)))
+                .set((
+                    sessions::token_hash.eq(key_hash(unkeyed_hash)),
+                    sessions::unkeyed_hash.eq(None::<String>),
+                ))
+                .execute(conn)?;
+        }
+
+        if !user_hashes.is_empty() || !session_hashes.is_empty() {
+            info!(
+                "Tokens of {} users and {} sessions are keyed",
+                user_hashes.len(),
+                session_hashes.len()
+            );
+        }
+
+        Ok(())
+    })
+}
+
+pub fn start() -> Worker {
+    Worker::spawn(
+        "Session monitor",
+        time::Duration::from_secs(config::CONFIG.sessions.touch_interval),
+        false,
+        || match db::Db::new() {
+            Ok(mut db) => {
+                if let Err(e) = write_last_seen(&mut db.conn).and_then(|_| purge(&mut db.conn)) {
+                    error!("Session monitor error: {}", e);
+                }
+            }
+            Err(e) => error!("Session monitor database error: {}", e),
+        },
+    )
+}
+
+fn write_last_seen(conn: &mut PgConnection) -> QueryResult<()> {
+    use crate::model::schema::sessions;
+
+    let seen = std::mem::take(&mut *SEEN.lock().unwrap());
+
+    for (id, ts) in seen {
+        diesel::update(sessions::table.filter(sessions::id.eq(id)))
+            .set(sessions::last_seen_ts.eq(ts))
+            .execute(conn)?;
+    }
+
+    Ok(())
+}
+
+fn purge(conn: &mut PgConnection) -> QueryResult<()> {
+    use crate::model::schema::sessions;
+
+    let hashes =
+        diesel::delete(sessions::table.filter(sessions::expire_ts.le(Utc::now().naive_utc())))
+            .returning(sessions::token_hash)
+            .get_results::<String>(conn)?;
+
+    hashes.iter().for_each(|h| user_cache::remove(h));
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use crate::config;
+use log::info;
+use std::sync::{LazyLock, OnceLock};
+use std::time;
+use tokio::sync::watch;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+    Signal,
+    Watchdog,
+}
+
+static REASON: LazyLock<watch::Sender<Option<Reason>>> = LazyLock::new(|| watch::channel(None).0);
+static REQUESTED: OnceLock<time::Instant> = OnceLock::new();
+
+// Can be called from any thread, the first reason wins
+pub fn request(reason: Reason) {
+    REASON.send_if_modified(|current| {
+        if current.is_none() {
+            *current = Some(reason);
+            REQUESTED.set(time::Instant::now()).ok();
+            true
+        } else {
+            false
+        }
+    });
+}
+
+pub fn reason() -> Option<Reason> {
+    *REASON.borrow()
+}
+
+// Time left until shutdown has to be finished, whole drain timeout if it is not requested.
+// Whatever is still running after that is abandoned.
+pub fn remaining() -> time::Duration {
+    let drain_timeout = time::Duration::from_secs(config::CONFIG.server.drain_timeout);
+
+    match REQUESTED.get() {
+        Some(requested) => {
+            (*requested + drain_timeout).saturating_duration_since(time::Instant::now())
+        }
+        None => drain_timeout,
+    }
+}
+
+// Completes on SIGTERM, SIGINT or explicit request
+pub async fn wait() {
+    let mut rx = REASON.subscribe();
+
+    tokio::select! {
+        name = signal() => {
+            info!("Received {}", name);
+            request(Reason::Signal);
+        }
+        _ = rx.wait_for(Option::is_some) => {}
+    }
+}
+
+#[cfg(unix)]
+async fn signal() -> &'static str {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen SIGTERM");
+    let mut interrupt = signal(SignalKind::interrupt()).expect("Failed to listen SIGINT");
+
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() -> &'static str {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("Failed to listen Ctrl-C");
+    "Ctrl-C"
+}
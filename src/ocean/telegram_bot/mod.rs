@@ -1,4 +1,5 @@
 use crate::config;
+use crate::metrics;
 use log::error;
 
 pub mod api;
@@ -42,6 +43,7 @@ async fn send_request(method: &str, params: serde_json::Value) -> serde_json::Va
     match res {
         Ok(r) => r,
         Err(e) => {
+            metrics::telegram_sent(false);
             error!("Telegram API request error: {:?}", e);
             serde_json::Value::Null
         }
@@ -62,6 +64,8 @@ async fn send(
         .json::<api::Response>()
         .await?;
 
+    metrics::telegram_sent(resp.ok);
+
     if !resp.ok {
         error!("Telegram API response error: {}", resp.description.unwrap());
         return Ok(serde_json::Value::Null);
@@ -1,21 +1,20 @@
 use crate::db;
+use crate::metrics;
+use crate::worker::Worker;
 use diesel::prelude::*;
-use log::info;
-use std::thread;
+use log::{error, info};
 use std::time;
 
-pub fn start() {
-    thread::spawn(|| {
-        let mut db = db::Db::new();
-        process_mandels(&mut db);
-
-        loop {
-            thread::sleep(time::Duration::from_secs(12 * 60 * 60)); // 12 hours
-            process_mandels(&mut db);
-        }
-    });
-
-    info!("Trash monitor started");
+pub fn start() -> Worker {
+    Worker::spawn(
+        "Trash monitor",
+        time::Duration::from_secs(12 * 60 * 60), // 12 hours
+        true,
+        || match db::Db::new() {
+            Ok(mut db) => process_mandels(&mut db),
+            Err(e) => error!("Trash monitor database error: {}", e),
+        },
+    )
 }
 
 fn process_mandels(db: &mut db::Db) {
@@ -46,4 +45,6 @@ fn process_mandels(db: &mut db::Db) {
         .execute(&mut db.conn).expect("Failed to restore mandels from trash");
 
     info!("Restored from trash {} mandels", restored);
+
+    metrics::trash_monitor_run(moved, restored);
 }
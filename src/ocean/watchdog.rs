@@ -1,13 +1,13 @@
 use crate::config;
 use crate::json_rpc;
+use crate::shutdown;
+use crate::worker::Worker;
 use log::{error, info};
-use std::thread;
 use std::time;
 use url::Url;
 
-pub fn start() {
-    thread::spawn(|| loop {
-        thread::sleep(time::Duration::from_secs(60));
+pub fn start() -> Worker {
+    Worker::spawn("Watchdog", time::Duration::from_secs(60), false, || {
         info!("Heartbeat");
 
         let client = reqwest::blocking::Client::builder()
@@ -15,27 +15,32 @@ pub fn start() {
             .build()
             .unwrap();
 
-        let mut url = Url::parse("https://localhost/api").unwrap();
+        let scheme = if config::CONFIG.server.ssl.is_some() {
+            "https"
+        } else {
+            "http"
+        };
+
+        let mut url = Url::parse(&format!("{}://localhost/api", scheme)).unwrap();
         url.set_port(Some(config::CONFIG.server.port)).unwrap();
-        url.set_query(Some(&format!(
-            "token={}",
-            config::CONFIG.watchdog.anonym_token
-        )));
 
         let req = json_rpc::Request {
+            jsonrpc: None,
             method: "ping".to_string(),
-            id: Some("1".to_string()),
+            id: Some(json_rpc::Id::String("1".to_string())),
             params: None,
         };
 
         let json = serde_json::to_value(&req).unwrap();
-        let resp = client.post(url).json(&json).send();
+        let resp = client
+            .post(url)
+            .bearer_auth(&config::CONFIG.watchdog.anonym_token)
+            .json(&json)
+            .send();
 
         if let Err(e) = resp {
             error!("Watchdog request error: {:?}", e);
-            std::process::exit(0);
+            shutdown::request(shutdown::Reason::Watchdog);
         }
-    });
-
-    info!("Watchdog started");
+    })
 }
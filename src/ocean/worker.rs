@@ -0,0 +1,50 @@
+use log::{error, info};
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+// Background thread that runs a task periodically until stopped
+pub struct Worker {
+    name: &'static str,
+    stop: mpsc::Sender<()>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl Worker {
+    pub fn spawn<F>(
+        name: &'static str,
+        interval: time::Duration,
+        run_first: bool,
+        mut task: F,
+    ) -> Self
+    where
+        F: FnMut() + Send + 'static,
+    {
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || {
+            if run_first {
+                task();
+            }
+
+            // Stop request interrupts waiting, but a running task is always finished
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                task();
+            }
+        });
+
+        info!("{} started", name);
+
+        Self { name, stop, handle }
+    }
+
+    pub fn stop(self) {
+        self.stop.send(()).ok();
+
+        if self.handle.join().is_err() {
+            error!("{} panicked", self.name);
+        } else {
+            info!("{} stopped", self.name);
+        }
+    }
+}